@@ -0,0 +1,109 @@
+use url::Url;
+use crate::model::UrlNormalization;
+
+/// Rewrites URLs into one canonical spelling so the frontier treats variants of
+/// the same page as one URL. Parsing with the `url` crate has already lowercased
+/// the host, dropped default ports and resolved `.` and `..` segments; this adds
+/// the rules that depend on how sites tend to use URLs.
+pub struct UrlNormalizer {
+    tracking_params: Vec<String>,
+    strip_trailing_slash: bool,
+    lowercase_path: bool,
+}
+
+impl UrlNormalizer {
+    pub fn new(config: &UrlNormalization) -> Self {
+        Self {
+            tracking_params: config.tracking_params.iter().map(|p| p.to_ascii_lowercase()).collect(),
+            strip_trailing_slash: config.strip_trailing_slash,
+            lowercase_path: config.lowercase_path,
+        }
+    }
+
+    pub fn normalize(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        // fragments never reach the server
+        url.set_fragment(None);
+        // `example.com.` and `example.com` are the same host
+        if let Some(host) = url.host_str()
+            && host.len() > 1
+            && let Some(trimmed) = host.strip_suffix('.')
+        {
+            let trimmed = trimmed.to_string();
+            let _ = url.set_host(Some(&trimmed));
+        }
+
+        let mut path = url.path().to_string();
+        if self.lowercase_path {
+            path = path.to_lowercase();
+        }
+        if self.strip_trailing_slash && path.len() > 1 && path.ends_with('/') {
+            path = path.trim_end_matches('/').to_string();
+            if path.is_empty() {
+                path.push('/');
+            }
+        }
+        if path != url.path() {
+            url.set_path(&path);
+        }
+
+        let query = url.query().map(|query| self.normalize_query(query));
+        url.set_query(query.as_deref().filter(|q| !q.is_empty()));
+        url
+    }
+
+    /// Drops tracking parameters and sorts the rest by name. Parameters are kept
+    /// as written, and repeated names keep their order, since servers may rely on it.
+    fn normalize_query(&self, query: &str) -> String {
+        let mut params: Vec<&str> = query.split('&')
+            .filter(|param| !param.is_empty())
+            .filter(|param| !self.is_tracking(param.split('=').next().unwrap_or("")))
+            .collect();
+        params.sort_by_key(|param| param.split('=').next().unwrap_or(""));
+        params.join("&")
+    }
+
+    fn is_tracking(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.tracking_params.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *pattern,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(config: &UrlNormalization, url: &str) -> String {
+        UrlNormalizer::new(config).normalize(&Url::parse(url).unwrap()).to_string()
+    }
+
+    #[test]
+    fn strip_trailing_slash() {
+        let config = UrlNormalization { strip_trailing_slash: true, ..UrlNormalization::default() };
+        assert_eq!(normalize(&config, "https://example.com/page/"), "https://example.com/page");
+        assert_eq!(normalize(&config, "https://example.com/docs//?b=2&a=1"), "https://example.com/docs?a=1&b=2");
+        // the root path keeps its slash
+        assert_eq!(normalize(&config, "https://example.com/"), "https://example.com/");
+
+        let default = UrlNormalization::default();
+        assert_eq!(normalize(&default, "https://example.com/page/"), "https://example.com/page/");
+    }
+
+    #[test]
+    fn lowercase_path() {
+        let config = UrlNormalization { lowercase_path: true, ..UrlNormalization::default() };
+        assert_eq!(normalize(&config, "https://example.com/Docs/Page.HTML?Q=A"), "https://example.com/docs/page.html?Q=A");
+
+        let default = UrlNormalization::default();
+        assert_eq!(normalize(&default, "https://example.com/Docs/Page.HTML"), "https://example.com/Docs/Page.HTML");
+    }
+
+    #[test]
+    fn both_options_merge_case_and_slash_variants() {
+        let config = UrlNormalization { strip_trailing_slash: true, lowercase_path: true, ..UrlNormalization::default() };
+        assert_eq!(normalize(&config, "https://example.com/Page/"), normalize(&config, "https://example.com/page"));
+    }
+}
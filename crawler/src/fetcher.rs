@@ -1,25 +1,34 @@
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, Response, StatusCode};
+use reqwest::header::{FROM, HeaderMap, HeaderValue};
 use url::Url;
 use std::sync::Arc;
 use crate::model::{CrawlConfig, CrawledPage};
+use crate::robots::MAX_ROBOTS_BYTES;
 use anyhow::Result;
 use chrono::Utc;
 use std::collections::HashMap;
+use std::fmt;
 
 pub struct Fetcher {
     client: Client,
-    config: Arc<CrawlConfig>,
+    max_response_bytes: usize,
 }
 
 impl Fetcher {
     pub fn new(config: Arc<CrawlConfig>) -> Self {
+        // site operators can reach us through the From header as well as the bot-info page
+        let mut headers = HeaderMap::new();
+        if let Ok(from) = HeaderValue::from_str(&config.contact_email) {
+            headers.insert(FROM, from);
+        }
         let client = Client::builder()
             .user_agent(format!("{} (+{})", config.user_agent, config.bot_info_url))
+            .default_headers(headers)
             .timeout(std::time::Duration::from_secs(30))
             .build()
             .unwrap();
         
-        Self { client, config }
+        Self { client, max_response_bytes: config.scope.max_response_bytes }
     }
 
     pub async fn fetch(&self, url: &Url, etag: Option<&str>, last_modified: Option<&str>) -> Result<Option<CrawledPage>> {
@@ -39,6 +48,12 @@ impl Fetcher {
         }
 
         let status = response.status().as_u16();
+        // an error page is not the page; its body is not worth reading
+        if !response.status().is_success() {
+            return Err(HttpStatusError(status).into());
+        }
+        // relative links resolve against where the page ended up, e.g. `/docs/` after a redirect from `/docs`
+        let final_url = response.url().clone();
         let mut headers = HashMap::new();
         for (name, value) in response.headers().iter() {
             headers.insert(name.to_string(), value.to_str().unwrap_or("").to_string());
@@ -47,11 +62,15 @@ impl Fetcher {
         let new_etag = response.headers().get("ETag").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let new_last_modified = response.headers().get("Last-Modified").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         
-        let html = response.text().await?;
+        let (body, truncated) = read_capped(response, self.max_response_bytes).await?;
+        if truncated {
+            eprintln!("Truncated {} at {} bytes", url, self.max_response_bytes);
+        }
+        let html = String::from_utf8_lossy(&body).into_owned();
         
         // Basic page model creation
         Ok(Some(CrawledPage {
-            url: url.clone(),
+            url: final_url,
             status_code: status,
             headers,
             html,
@@ -61,6 +80,48 @@ impl Fetcher {
             content_hash: String::new(), // To be filled by hasher
             etag: new_etag,
             last_modified: new_last_modified,
+            canonical: None,             // To be filled by parser
+            fingerprint: None,           // To be filled by parser
         }))
     }
+
+    /// Fetches a robots.txt file, returning the final status after redirects and
+    /// the body, truncated to the size the parser reads.
+    pub async fn fetch_robots(&self, url: &Url) -> Result<(u16, String)> {
+        let (status, body) = self.fetch_bytes(url, MAX_ROBOTS_BYTES).await?;
+        Ok((status, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Fetches a URL without conditional headers, reading at most `max_bytes` of the body.
+    pub async fn fetch_bytes(&self, url: &Url, max_bytes: usize) -> Result<(u16, Vec<u8>)> {
+        let response = self.client.get(url.as_str()).send().await?;
+        let status = response.status().as_u16();
+        let (body, _) = read_capped(response, max_bytes).await?;
+        Ok((status, body))
+    }
+}
+
+/// A response that was neither a success nor 304 Not Modified, after redirects.
+#[derive(Debug)]
+pub struct HttpStatusError(pub u16);
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP status {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Reads at most `max_bytes` of a response body, and says whether there was more.
+async fn read_capped(mut response: Response, max_bytes: usize) -> Result<(Vec<u8>, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > max_bytes {
+            body.truncate(max_bytes);
+            return Ok((body, true));
+        }
+    }
+    Ok((body, false))
 }
@@ -1,41 +1,355 @@
-use std::collections::{VecDeque, HashSet};
-use url::Url;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use url::Url;
 use tokio::sync::Mutex;
+use crate::canonical::UrlNormalizer;
+use crate::scope::Scope;
+use crate::seen::SeenSet;
+use crate::store::{url_key, CrawlStore, Resumed, UrlKey};
 
-pub struct URLFrontier {
-    queue: Mutex<VecDeque<Url>>,
-    seen: Mutex<HashSet<Url>>,
+/// Sitemap priority assumed for URLs that do not state one.
+pub const DEFAULT_PRIORITY: f32 = 0.5;
+/// Priority of a page queued for a revisit: above any sitemap priority, so a due
+/// revisit goes before new pages of its host.
+pub const REVISIT_PRIORITY: f32 = 2.0;
+/// Score lost for each link followed away from a seed or sitemap.
+const DEPTH_WEIGHT: f32 = 0.1;
+/// Score gained for links from other pages, on a log scale.
+const LINK_WEIGHT: f32 = 0.2;
+/// A URL already queued is queued again, at its higher score, only once more
+/// links have raised its score by this much...
+const SCORE_STEP: f32 = 0.05;
+/// ...and only while it has fewer than this many copies in its host's queue.
+/// Between the two, a URL linked from many pages takes a few heap slots rather
+/// than one per link.
+const MAX_COPIES: u32 = 4;
+/// Longest a worker is told to wait before asking again, so URLs queued for
+/// other hosts in the meantime are picked up promptly.
+const IDLE_POLL: Duration = Duration::from_millis(200);
+
+/// A URL waiting to be crawled, with what its sitemap said about it, if anything.
+#[derive(Debug, Clone)]
+pub struct FrontierEntry {
+    pub url: Url,
+    pub lastmod: Option<DateTime<Utc>>,
+    pub priority: f32,
+    /// Links followed from a seed or sitemap to reach this URL.
+    pub depth: u32,
 }
 
-impl URLFrontier {
-    pub fn new() -> Self {
+impl FrontierEntry {
+    pub fn new(url: Url) -> Self {
+        Self { url, lastmod: None, priority: DEFAULT_PRIORITY, depth: 0 }
+    }
+
+    fn score(&self, inlinks: u32) -> f32 {
+        self.priority + LINK_WEIGHT * (inlinks as f32).ln_1p() - DEPTH_WEIGHT * self.depth as f32
+    }
+}
+
+/// What the frontier has for a worker.
+pub enum Next {
+    /// A URL whose host no other worker holds and whose delay has passed.
+    Ready(FrontierEntry),
+    /// Every queued host is still waiting out its delay or is held by another
+    /// worker that may queue more; ask again after this long.
+    Wait(Duration),
+    /// Nothing is queued and no worker holds a host, so nothing more will come.
+    Exhausted,
+}
+
+/// A URL in a host's queue, ordered by score and then by arrival. The entry
+/// itself is kept in `Queues::pending`, under the URL's key.
+struct Queued {
+    score: f32,
+    /// Arrival order; a copy older than the URL's latest is stale.
+    seq: u64,
+    key: UrlKey,
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+struct Pending {
+    entry: FrontierEntry,
+    inlinks: u32,
+    /// Score and arrival order of the URL's latest copy in its host's queue.
+    score: f32,
+    seq: u64,
+    /// Copies of the URL in its host's queue, stale ones included.
+    copies: u32,
+}
+
+struct Queues {
+    seen: Box<dyn SeenSet>,
+    /// URLs queued and not yet handed out.
+    pending: HashMap<UrlKey, Pending>,
+    /// Host -> its URLs, best first.
+    hosts: HashMap<String, BinaryHeap<Queued>>,
+    /// Hosts with queued URLs that no worker holds, by when they may next be hit.
+    ready: BinaryHeap<Reverse<(Instant, String)>>,
+    /// Hosts handed to a worker and not yet released.
+    busy: HashSet<String>,
+    /// Host -> when it may next be hit, for hosts whose queue ran dry.
+    not_before: HashMap<String, Instant>,
+    /// Host -> URLs admitted, counted against the page budgets.
+    pages: HashMap<String, usize>,
+    total_pages: usize,
+    seq: u64,
+}
+
+impl Queues {
+    fn new(seen: Box<dyn SeenSet>) -> Self {
         Self {
-            queue: Mutex::new(VecDeque::new()),
-            seen: Mutex::new(HashSet::new()),
+            seen,
+            pending: HashMap::new(),
+            hosts: HashMap::new(),
+            ready: BinaryHeap::new(),
+            busy: HashSet::new(),
+            not_before: HashMap::new(),
+            pages: HashMap::new(),
+            total_pages: 0,
+            seq: 0,
+        }
+    }
+
+    /// Checks a URL not seen before against the scope and the page budgets,
+    /// counting it towards them if it is let in.
+    fn admit(&mut self, scope: &Scope, entry: &FrontierEntry) -> bool {
+        if !scope.allows(&entry.url, entry.depth) || scope.max_pages.is_some_and(|max| self.total_pages >= max) {
+            return false;
+        }
+        let host = host_key(&entry.url);
+        let pages = self.pages.entry(host.clone()).or_default();
+        if scope.max_pages_per_host.is_some_and(|max| *pages >= max) {
+            return false;
+        }
+        *pages += 1;
+        self.total_pages += 1;
+        if scope.max_pages_per_host == Some(*pages) {
+            println!("Page budget reached for {}", host);
+        }
+        if scope.max_pages == Some(self.total_pages) {
+            println!("Page budget reached for the crawl");
+        }
+        true
+    }
+
+    fn add(&mut self, entry: FrontierEntry) -> bool {
+        if self.seen.insert(&entry.url) {
+            self.insert(entry);
+            return true;
+        }
+        // another link to a URL still waiting raises its score. Once it has risen
+        // a step the URL is queued again; the copy already queued goes stale and
+        // is dropped when it reaches the top
+        let key = url_key(&entry.url);
+        let Some(pending) = self.pending.get_mut(&key) else { return false };
+        pending.inlinks += 1;
+        pending.entry.depth = pending.entry.depth.min(entry.depth);
+        pending.entry.priority = pending.entry.priority.max(entry.priority);
+        let score = pending.entry.score(pending.inlinks);
+        if score < pending.score + SCORE_STEP || pending.copies >= MAX_COPIES {
+            return false;
+        }
+        pending.score = score;
+        pending.copies += 1;
+        let seq = self.push(host_key(&entry.url), key, score);
+        if let Some(pending) = self.pending.get_mut(&key) {
+            pending.seq = seq;
+        }
+        false
+    }
+
+    /// Queues a page crawled before, bypassing the seen-set and the budgets it
+    /// was already counted against. Returns false if the URL is still queued.
+    fn requeue(&mut self, entry: FrontierEntry) -> bool {
+        if self.pending.contains_key(&url_key(&entry.url)) {
+            return false;
+        }
+        self.insert(entry);
+        true
+    }
+
+    /// Queues a URL that is not queued yet.
+    fn insert(&mut self, entry: FrontierEntry) {
+        let (key, score) = (url_key(&entry.url), entry.score(0));
+        let seq = self.push(host_key(&entry.url), key, score);
+        self.pending.insert(key, Pending { entry, inlinks: 0, score, seq, copies: 1 });
+    }
+
+    /// Adds a copy of the URL with `key` to the queue of `host`, returning its arrival order.
+    fn push(&mut self, host: String, key: UrlKey, score: f32) -> u64 {
+        self.seq += 1;
+        let queued = Queued { score, seq: self.seq, key };
+        let queue = self.hosts.entry(host.clone()).or_default();
+        let was_empty = queue.is_empty();
+        queue.push(queued);
+        if was_empty && !self.busy.contains(&host) {
+            let at = self.not_before.remove(&host).unwrap_or_else(Instant::now);
+            self.ready.push(Reverse((at, host)));
+        }
+        self.seq
+    }
+
+    fn pop(&mut self) -> Next {
+        let now = Instant::now();
+        while let Some(Reverse((at, _))) = self.ready.peek() {
+            if *at > now {
+                return Next::Wait((*at - now).min(IDLE_POLL));
+            }
+            let Some(Reverse((_, host))) = self.ready.pop() else { break };
+            while let Some(queued) = self.hosts.get_mut(&host).and_then(|q| q.pop()) {
+                let Some(pending) = self.pending.get_mut(&queued.key) else { continue };
+                if pending.seq != queued.seq {
+                    // saturating: the copy may be left over from before the URL was last handed out
+                    pending.copies = pending.copies.saturating_sub(1);
+                    continue;
+                }
+                if let Some(pending) = self.pending.remove(&queued.key) {
+                    self.busy.insert(host);
+                    return Next::Ready(pending.entry);
+                }
+            }
+            // only stale copies were left
+            self.hosts.remove(&host);
+        }
+        if self.busy.is_empty() {
+            Next::Exhausted
+        } else {
+            Next::Wait(IDLE_POLL)
+        }
+    }
+
+    fn release(&mut self, host: String, ready_at: Instant) {
+        self.busy.remove(&host);
+        if self.hosts.get(&host).is_some_and(|q| !q.is_empty()) {
+            self.ready.push(Reverse((ready_at, host)));
+        } else {
+            self.hosts.remove(&host);
+            if ready_at > Instant::now() {
+                self.not_before.insert(host, ready_at);
+            }
         }
     }
+}
 
-    pub async fn add_url(&self, url: Url) {
-        let mut seen = self.seen.lock().await;
-        if seen.insert(url.clone()) {
-            let mut queue = self.queue.lock().await;
-            queue.push_back(url);
+/// Mercator-style frontier: each host has its own queue ordered by URL score
+/// (sitemap priority, links seen to the URL, and depth), and a heap of hosts by
+/// the time they may next be hit decides which queue serves the next worker.
+/// A host is held by one worker at a time, so one large site cannot occupy
+/// every worker while other hosts wait.
+pub struct URLFrontier {
+    queues: Mutex<Queues>,
+    store: Arc<CrawlStore>,
+    scope: Arc<Scope>,
+    normalizer: Arc<UrlNormalizer>,
+}
+
+impl URLFrontier {
+    /// Starts from whatever a previous run left queued; newly queued URLs are
+    /// recorded in `store` so a later run can pick them up in turn. URLs admitted
+    /// by earlier runs count towards the page budgets of `scope`. Queued URLs
+    /// are remembered in the resumed seen-set, which may trade exactness for memory.
+    pub fn new(
+        store: Arc<CrawlStore>,
+        resumed: Resumed,
+        scope: Arc<Scope>,
+        normalizer: Arc<UrlNormalizer>,
+    ) -> Self {
+        let mut queues = Queues::new(resumed.seen);
+        queues.total_pages = resumed.pages.values().sum();
+        queues.pages = resumed.pages;
+        for entry in resumed.pending {
+            queues.insert(entry);
         }
+        Self { queues: Mutex::new(queues), store, scope, normalizer }
     }
 
     pub async fn add_urls(&self, urls: Vec<Url>) {
-        let mut seen = self.seen.lock().await;
-        let mut queue = self.queue.lock().await;
-        for url in urls {
-            if seen.insert(url.clone()) {
-                queue.push_back(url);
+        self.add_entries(urls.into_iter().map(FrontierEntry::new).collect()).await;
+    }
+
+    /// Queues the links found on a page that was `depth` links from a seed.
+    pub async fn add_links(&self, links: Vec<Url>, depth: u32) {
+        let entries = links.into_iter()
+            .map(|url| FrontierEntry { depth: depth + 1, ..FrontierEntry::new(url) })
+            .collect();
+        self.add_entries(entries).await;
+    }
+
+    /// Normalizes the entries' URLs and queues those that are in scope and
+    /// within the page budgets.
+    pub async fn add_entries(&self, entries: Vec<FrontierEntry>) {
+        let mut queues = self.queues.lock().await;
+        // a page linking the same URL twice counts once
+        let mut batch = HashSet::new();
+        for mut entry in entries {
+            entry.url = self.normalizer.normalize(&entry.url);
+            if !batch.insert(entry.url.clone()) {
+                continue;
+            }
+            if !queues.seen.contains(&entry.url) && !queues.admit(&self.scope, &entry) {
+                continue;
+            }
+            if queues.add(entry.clone()) {
+                self.store.enqueued(&entry);
             }
         }
     }
 
-    pub async fn next_url(&self) -> Option<Url> {
-        let mut queue = self.queue.lock().await;
-        queue.pop_front()
+    /// Queues a page that is due for a revisit on its host's queue, so it waits
+    /// for the host like any other URL. It is not logged as queued: a later run
+    /// schedules it again from the crawl state.
+    pub async fn add_revisit(&self, entry: FrontierEntry) {
+        self.queues.lock().await.requeue(entry);
+    }
+
+    /// Puts back a URL that was handed out but could not be fetched yet, and
+    /// gives back its host, which may be hit again from `ready_at`. The URL
+    /// stays logged as queued, so a later run picks it up too.
+    pub async fn retry(&self, entry: FrontierEntry, ready_at: Instant) {
+        let mut queues = self.queues.lock().await;
+        let host = host_key(&entry.url);
+        queues.requeue(entry);
+        queues.release(host, ready_at);
+    }
+
+    /// Hands out the best URL of the host that has been ready the longest. The
+    /// host stays with the caller until it is given back with [`Self::release`].
+    pub async fn next_url(&self) -> Next {
+        self.queues.lock().await.pop()
     }
+
+    /// Gives back the host of `url`, which may be hit again from `ready_at`.
+    /// Links found on the page should be added first, so other workers do not
+    /// see an empty frontier in between and stop.
+    pub async fn release(&self, url: &Url, ready_at: Instant) {
+        self.queues.lock().await.release(host_key(url), ready_at);
+    }
+}
+
+/// Hosts are partitioned the same way politeness rate-limits them.
+pub(crate) fn host_key(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_string()
 }
@@ -1,64 +1,269 @@
 mod model;
+mod canonical;
 mod politeness;
+mod recrawl;
 mod frontier;
 mod fetcher;
 mod parser;
+mod robots;
+mod scope;
+mod seen;
+mod simhash;
+mod sitemap;
+mod store;
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use chrono::Utc;
 use url::Url;
-use crate::model::CrawlConfig;
-use crate::politeness::PolitenessManager;
-use crate::frontier::URLFrontier;
-use crate::fetcher::Fetcher;
+use crate::canonical::UrlNormalizer;
+use crate::model::{CrawlConfig, RecrawlBounds, SeenSetConfig};
+use crate::politeness::{Access, PolitenessManager};
+use crate::frontier::{DEFAULT_PRIORITY, FrontierEntry, Next, REVISIT_PRIORITY, URLFrontier};
+use crate::fetcher::{Fetcher, HttpStatusError};
 use crate::parser::Parser;
+use crate::recrawl::{Freshness, RecrawlScheduler};
+use crate::scope::Scope;
+use crate::simhash::FingerprintIndex;
+use crate::store::{ChangeHistory, CrawlStore};
+
+const DEFAULT_BLOOM_FP_RATE: f64 = 0.001;
+const BLOOM_INITIAL_CAPACITY: usize = 1_000_000;
+
+const USAGE: &str = "Usage: web-crawler [OPTIONS] [SEED_URL]...
+  --bloom[=RATE]              remember seen URLs in a Bloom filter with this false-positive rate (default 0.001)
+  --allow-host=HOST           only crawl HOST and its subdomains; repeatable
+  --deny-host=HOST            never crawl HOST or its subdomains; repeatable
+  --include=REGEX             only crawl URLs matching one of these; repeatable
+  --exclude=REGEX             never crawl URLs matching any of these; repeatable
+  --max-depth=N               do not follow links more than N deep from a seed or sitemap
+  --max-pages-per-host=N      queue at most N URLs per host
+  --max-pages=N               queue at most N URLs in all
+  --max-response-bytes=N      cut response bodies off after N bytes
+  --recrawl-interval=TIME     first revisit pages TIME after their last visit, or `off` (default 24h)
+  --recrawl-bounds=MIN..MAX   keep revisit intervals between MIN and MAX (default 1h..30d)
+  --recrawl-bounds=HOST=MIN..MAX
+                              the same for HOST only; repeatable
+  --recrawl-budget=N          revisit at most N pages per hour, 0 for no limit (default 1200)
+  --no-follow-duplicate-links do not follow links on near-duplicate pages
+  --strip-trailing-slash      treat /page/ and /page as the same URL
+  --lowercase-path            treat URL paths case-insensitively";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 1. Initialize configuration and components
-    let config = Arc::new(CrawlConfig::default());
-    let frontier = Arc::new(URLFrontier::new());
-    let politeness = Arc::new(PolitenessManager::new(config.clone()));
+    let mut config = CrawlConfig::default();
+    let seeds = parse_args(&mut config, std::env::args().skip(1))?;
+    let config = Arc::new(config);
+    let (store, resumed) = CrawlStore::open(&config.state_path, seen::from_config(config.seen_set)?)?;
+    let seen: usize = resumed.pages.values().sum();
+    if seen > 0 {
+        println!("Resuming crawl: {} URLs seen, {} still queued", seen, resumed.pending.len());
+    }
+    let store = Arc::new(store);
+    let scope = Arc::new(Scope::new(&config.scope)?);
+    let normalizer = Arc::new(UrlNormalizer::new(&config.normalization));
+    let frontier = Arc::new(URLFrontier::new(store.clone(), resumed, scope.clone(), normalizer.clone()));
     let fetcher = Arc::new(Fetcher::new(config.clone()));
+    let politeness = Arc::new(PolitenessManager::new(config.clone(), fetcher.clone()));
 
-    // Seed the frontier
-    frontier.add_url(Url::parse("https://rust-lang.org")?).await;
+    // Seed the frontier from the command line, then from each seed site's sitemaps
+    for seed in seeds.iter().filter(|seed| !scope.allows(seed, 0)) {
+        eprintln!("Seed {} is outside the crawl scope and will not be crawled", seed);
+    }
+    frontier.add_urls(seeds.clone()).await;
+    for seed in &seeds {
+        let mut entries: Vec<FrontierEntry> = sitemap::discover(seed, &fetcher, &politeness).await
+            .into_iter()
+            .map(|e| FrontierEntry { lastmod: e.lastmod, priority: e.priority.unwrap_or(DEFAULT_PRIORITY), ..FrontierEntry::new(e.url) })
+            .collect();
+        // the frontier serves equal scores in arrival order, so freshest pages go first among equals
+        entries.sort_by_key(|e| std::cmp::Reverse(e.lastmod));
+        println!("Seeding {} URLs from sitemaps of {}", entries.len(), seed);
+        frontier.add_entries(entries).await;
+    }
 
     println!("Starting crawler...");
 
+    // Pages crawled by earlier runs come due again relative to their last visit,
+    // and are what new pages are compared against
+    let recrawl = config.recrawl_interval.map(|interval| RecrawlScheduler::new(config.clone(), interval));
+    let fingerprints = FingerprintIndex::new(config.near_duplicates.max_distance);
+    store.for_each(|url, state| {
+        if let Some(fingerprint) = state.fingerprint.filter(|_| state.duplicate_of.is_none() && state.canonical.is_none()) {
+            fingerprints.insert(url, fingerprint);
+        }
+        // pages that the scope no longer covers are left alone
+        if let Some(scheduler) = &recrawl
+            && state.revisitable()
+            && scope.allows(url, state.depth)
+        {
+            let interval = scheduler.interval(url, &state.history);
+            scheduler.schedule(url, state.updated, interval);
+        }
+    });
+    let recrawl = recrawl.map(Arc::new);
+    let fingerprints = Arc::new(fingerprints);
+    let follow_duplicate_links = config.near_duplicates.follow_links;
+
     // 2. Spawn workers
     let mut workers = Vec::new();
     for i in 0..config.max_concurrent_requests {
         let frontier = frontier.clone();
         let politeness = politeness.clone();
         let fetcher = fetcher.clone();
+        let store = store.clone();
+        let recrawl = recrawl.clone();
+        let normalizer = normalizer.clone();
+        let fingerprints = fingerprints.clone();
         
         let worker = tokio::spawn(async move {
             println!("Worker {} started", i);
-            while let Some(url) = frontier.next_url().await {
+            loop {
+                // Due revisits join their host's queue ahead of newly discovered
+                // pages. The URL handed out holds its host until it is released below.
+                if let Some(recrawl) = &recrawl {
+                    while let Some(key) = recrawl.next_due() {
+                        // links on the page are as deep as when it was first crawled
+                        let Some((url, state)) = store.lookup(&key) else { continue };
+                        let depth = state.depth;
+                        frontier.add_revisit(FrontierEntry { priority: REVISIT_PRIORITY, depth, ..FrontierEntry::new(url) }).await;
+                    }
+                }
+                let entry = match frontier.next_url().await {
+                    Next::Ready(entry) => entry,
+                    Next::Wait(wait) => {
+                        tokio::time::sleep(wait).await;
+                        continue;
+                    }
+                    Next::Exhausted => match recrawl.as_ref().and_then(|r| r.time_until_next()) {
+                        // poll so links found meanwhile by other workers are not left waiting
+                        Some(wait) => {
+                            tokio::time::sleep(wait.min(Duration::from_secs(1))).await;
+                            continue;
+                        }
+                        None => break,
+                    },
+                };
+
+                let (url, depth) = (entry.url.clone(), entry.depth);
+
                 // Respect robots.txt
-                if !politeness.can_crawl(&url).await {
-                    continue;
+                match politeness.can_crawl(&url).await {
+                    Access::Allowed => {}
+                    Access::Disallowed => {
+                        store.skipped(&url, depth, "robots.txt");
+                        frontier.release(&url, Instant::now()).await;
+                        continue;
+                    }
+                    // robots.txt may only be unreachable for now; try the URL again
+                    // once it is fetched again rather than giving up on it
+                    Access::Unavailable { retry_at } => {
+                        frontier.retry(entry, retry_at).await;
+                        continue;
+                    }
                 }
 
                 // Respect per-domain rate limit
                 politeness.wait_for_politeness(&url).await;
+                let next_hit = Instant::now() + politeness.delay_for(&url);
 
                 println!("Worker {} fetching: {}", i, url);
 
-                // Fetch the page with conditional headers
-                match fetcher.fetch(&url, None, None).await {
+                // Fetch the page with the validators from its last visit, if any
+                let previous = store.state(&url);
+                let etag = previous.as_ref().and_then(|p| p.etag.as_deref());
+                let last_modified = previous.as_ref().and_then(|p| p.last_modified.as_deref());
+                let freshness = match fetcher.fetch(&url, etag, last_modified).await {
                     Ok(Some(mut page)) => {
                         // Parse page content and extract links
                         Parser::parse(&mut page);
                         println!("Worker {} found {} links", i, page.outgoing_links.len());
 
-                        // Add new links to frontier
-                        frontier.add_urls(page.outgoing_links).await;
+                        let unchanged = previous.as_ref()
+                            .and_then(|p| p.content_hash.as_deref())
+                            .is_some_and(|hash| hash == page.content_hash);
 
-                        // TODO: Save to storage (S3/Postgres)
+                        // A page naming another URL as canonical is a copy of that page:
+                        // queue the canonical URL instead and do not revisit this one
+                        let canonical = page.canonical.as_ref()
+                            .map(|c| normalizer.normalize(c))
+                            .filter(|c| *c != normalizer.normalize(&url));
+
+                        // Mirrors and print views are crawled once and indexed once. A
+                        // canonicalized copy is not fingerprinted, so it cannot stand in
+                        // as the original for its canonical page
+                        let duplicate_of = page.fingerprint
+                            .filter(|_| canonical.is_none())
+                            .and_then(|fp| fingerprints.insert(&url, fp));
+                        if let Some(original) = &duplicate_of {
+                            println!("Worker {} found {} is a near-duplicate of {}", i, url, original);
+                        }
+
+                        // Add new links to frontier before marking the page done, so
+                        // a crash in between re-fetches the page rather than losing its links
+                        let links = std::mem::take(&mut page.outgoing_links);
+                        if duplicate_of.is_none() || follow_duplicate_links {
+                            frontier.add_links(links, depth).await;
+                        }
+                        if let Some(canonical) = &canonical {
+                            println!("Worker {} found {} is canonically {}", i, url, canonical);
+                            frontier.add_entries(vec![FrontierEntry { depth, ..FrontierEntry::new(canonical.clone()) }]).await;
+                        }
+                        store.fetched(&url, depth, &page, duplicate_of, canonical.clone());
+
+                        // TODO: Save to storage (S3/Postgres), leaving out near-duplicates so they are not indexed
+                        match canonical {
+                            Some(_) => None,
+                            None => Some(if unchanged { Freshness::Unchanged } else { Freshness::Changed }),
+                        }
+                    }
+                    Ok(None) => {
+                        store.not_modified(&url);
+                        Some(Freshness::Unchanged)
                     }
-                    Ok(None) => println!("Worker {} page not modified: {}", i, url),
-                    Err(e) => eprintln!("Worker {} failed to fetch {}: {}", i, url, e),
+                    Err(e) => {
+                        eprintln!("Worker {} failed to fetch {}: {}", i, url, e);
+                        // An error page or a timeout says nothing about whether the page
+                        // changed: keep what the last good fetch found, and back off
+                        let http_status = e.downcast_ref::<HttpStatusError>().map(|e| e.0);
+                        let interval = recrawl.as_ref().map(|recrawl| {
+                            let history = previous.as_ref().map(|p| p.history.clone()).unwrap_or_default();
+                            recrawl.adapt(&url, recrawl.interval(&url, &history), Freshness::Unchanged)
+                        });
+                        store.failed(&url, depth, http_status, e.to_string(), interval);
+                        if let (Some(recrawl), Some(interval)) = (&recrawl, interval) {
+                            recrawl.schedule(&url, Utc::now(), interval);
+                        }
+                        None
+                    }
+                };
+
+                // The page's links are queued by now, so no worker can find the
+                // frontier empty and quit while more URLs are on their way
+                frontier.release(&url, next_hit).await;
+                // no freshness: the page has been rescheduled already or is not revisited
+                let Some(freshness) = freshness else { continue };
+
+                if let Some(recrawl) = &recrawl {
+                    // only a page fetched before can be compared with its last visit
+                    let interval = match previous.filter(|p| p.content_hash.is_some()) {
+                        Some(previous) => {
+                            let current = recrawl.interval(&url, &previous.history);
+                            let interval = recrawl.adapt(&url, current, freshness);
+                            store.revisited(&url, freshness == Freshness::Changed, interval);
+                            let (revisits, unchanged) = recrawl.record(freshness);
+                            println!(
+                                "Worker {} revisited {}: {}, next visit in {:.1}h ({} of {} revisits unchanged)",
+                                i, url, if freshness == Freshness::Unchanged { "unchanged" } else { "changed" },
+                                interval.as_secs_f64() / 3600.0, unchanged, revisits,
+                            );
+                            interval
+                        }
+                        None => recrawl.interval(&url, &ChangeHistory::default()),
+                    };
+                    recrawl.schedule(&url, Utc::now(), interval);
                 }
             }
         });
@@ -72,3 +277,88 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Applies command-line options to `config` and returns the seed URLs, or the
+/// default seed if none were given.
+fn parse_args(config: &mut CrawlConfig, args: impl Iterator<Item = String>) -> anyhow::Result<Vec<Url>> {
+    let mut seeds = Vec::new();
+    for arg in args {
+        if !arg.starts_with("--") {
+            seeds.push(Url::parse(&arg).with_context(|| format!("invalid seed URL {:?}", arg))?);
+            continue;
+        }
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let scope = &mut config.scope;
+        match (name, value) {
+            // trades an exact seen-set for a Bloom filter, for huge crawls
+            ("--bloom", rate) => {
+                let false_positive_rate = match rate {
+                    Some(rate) => rate.parse().with_context(|| format!("invalid false-positive rate {:?}", rate))?,
+                    None => DEFAULT_BLOOM_FP_RATE,
+                };
+                config.seen_set = SeenSetConfig::Bloom { initial_capacity: BLOOM_INITIAL_CAPACITY, false_positive_rate };
+            }
+            ("--allow-host", Some(host)) => scope.allowed_hosts.push(host.to_string()),
+            ("--deny-host", Some(host)) => scope.denied_hosts.push(host.to_string()),
+            ("--include", Some(pattern)) => scope.include_patterns.push(pattern.to_string()),
+            ("--exclude", Some(pattern)) => scope.exclude_patterns.push(pattern.to_string()),
+            ("--max-depth", Some(n)) => scope.max_depth = Some(parse_number(name, n)?),
+            ("--max-pages-per-host", Some(n)) => scope.max_pages_per_host = Some(parse_number(name, n)?),
+            ("--max-pages", Some(n)) => scope.max_pages = Some(parse_number(name, n)?),
+            ("--max-response-bytes", Some(n)) => scope.max_response_bytes = parse_number(name, n)?,
+            ("--recrawl-interval", Some("off")) => config.recrawl_interval = None,
+            ("--recrawl-interval", Some(time)) => config.recrawl_interval = Some(parse_duration(name, time)?),
+            ("--recrawl-bounds", Some(bounds)) => match bounds.split_once('=') {
+                Some((host, bounds)) => {
+                    config.site_recrawl_bounds.insert(host.to_ascii_lowercase(), parse_bounds(name, bounds)?);
+                }
+                None => config.recrawl_bounds = parse_bounds(name, bounds)?,
+            },
+            ("--recrawl-budget", Some(n)) => config.recrawl_budget = Some(parse_number(name, n)?).filter(|&n| n > 0),
+            ("--no-follow-duplicate-links", None) => config.near_duplicates.follow_links = false,
+            ("--strip-trailing-slash", None) => config.normalization.strip_trailing_slash = true,
+            ("--lowercase-path", None) => config.normalization.lowercase_path = true,
+            _ => anyhow::bail!("unknown option {:?}\n{}", arg, USAGE),
+        }
+    }
+    if seeds.is_empty() {
+        seeds.push(Url::parse("https://rust-lang.org")?);
+    }
+    Ok(seeds)
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> anyhow::Result<T> {
+    value.parse().map_err(|_| anyhow::anyhow!("{} expects a whole number, got {:?}", option, value))
+}
+
+/// Parses a time like `90s`, `30m`, `6h` or `7d`; a bare number is seconds.
+fn parse_duration(option: &str, value: &str) -> anyhow::Result<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => anyhow::bail!("{} expects a time like 90s, 30m, 6h or 7d, got {:?}", option, value),
+    };
+    let secs: u64 = parse_number(option, number)?;
+    Ok(Duration::from_secs(secs.saturating_mul(unit)))
+}
+
+/// Parses `MIN..MAX`, two times as taken by [`parse_duration`].
+fn parse_bounds(option: &str, value: &str) -> anyhow::Result<RecrawlBounds> {
+    let Some((min, max)) = value.split_once("..") else {
+        anyhow::bail!("{} expects MIN..MAX, got {:?}", option, value);
+    };
+    let bounds = RecrawlBounds { min: parse_duration(option, min)?, max: parse_duration(option, max)? };
+    if bounds.min > bounds.max {
+        anyhow::bail!("{} has its minimum above its maximum: {:?}", option, value);
+    }
+    Ok(bounds)
+}
@@ -5,6 +5,7 @@ use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CrawledPage {
+    /// Where the page was served from, after any redirects.
     pub url: Url,
     pub status_code: u16,
     pub headers: HashMap<String, String>,
@@ -15,6 +16,10 @@ pub struct CrawledPage {
     pub content_hash: String,
     pub etag: Option<String>,
     pub last_modified: Option<String>,
+    /// The page's `<link rel="canonical">`, if it names one.
+    pub canonical: Option<Url>,
+    /// SimHash of `text_content`, for spotting near-duplicate pages.
+    pub fingerprint: Option<u64>,
 }
 
 pub struct CrawlConfig {
@@ -23,6 +28,113 @@ pub struct CrawlConfig {
     pub contact_email: String,
     pub default_delay: std::time::Duration,
     pub max_concurrent_requests: usize,
+    /// How long a fetched robots.txt is trusted before it is fetched again.
+    pub robots_ttl: std::time::Duration,
+    /// How long a host stays disallowed after its robots.txt failed with a 5xx or network error.
+    pub robots_error_ttl: std::time::Duration,
+    /// Upper bound on a site's `Crawl-delay`, so one host cannot stall a worker indefinitely.
+    pub max_crawl_delay: std::time::Duration,
+    /// Append-only log of the frontier and per-URL crawl results, used to resume.
+    pub state_path: std::path::PathBuf,
+    /// How long after its first visit a page is fetched again; later intervals adapt to how
+    /// often the page is seen to change. `None` ends the crawl once the frontier drains.
+    pub recrawl_interval: Option<std::time::Duration>,
+    /// Limits on the adaptive revisit interval.
+    pub recrawl_bounds: RecrawlBounds,
+    /// Per-host overrides of `recrawl_bounds`.
+    pub site_recrawl_bounds: HashMap<String, RecrawlBounds>,
+    /// Revisits allowed per hour across all sites; `None` revisits pages as soon as they are due.
+    pub recrawl_budget: Option<u32>,
+    /// Which URLs the crawl may follow and how much of them it may fetch.
+    pub scope: CrawlScope,
+    /// How URLs are rewritten before they are checked against those already seen.
+    pub normalization: UrlNormalization,
+    /// How the frontier remembers the URLs it has queued.
+    pub seen_set: SeenSetConfig,
+    /// How pages nearly identical to one already crawled are treated.
+    pub near_duplicates: NearDuplicates,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NearDuplicates {
+    /// Most SimHash bits two pages may differ in and still count as duplicates (at most 7).
+    pub max_distance: u32,
+    /// Whether links on a near-duplicate page are followed.
+    pub follow_links: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SeenSetConfig {
+    /// Every URL kept in memory: exact, but memory grows with the crawl.
+    Exact,
+    /// A scalable Bloom filter, using a few bytes per URL. Up to
+    /// `false_positive_rate` of new URLs are wrongly taken as seen and never crawled.
+    Bloom { initial_capacity: usize, false_positive_rate: f64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct UrlNormalization {
+    /// Query parameters dropped as tracking noise; a trailing `*` matches any suffix.
+    pub tracking_params: Vec<String>,
+    /// Treat `/docs/` and `/docs` as the same page. Off by default: servers
+    /// commonly redirect `/docs` back to `/docs/`, costing a request per page.
+    pub strip_trailing_slash: bool,
+    /// Treat paths case-insensitively, for sites served from case-insensitive file systems.
+    pub lowercase_path: bool,
+}
+
+impl Default for UrlNormalization {
+    fn default() -> Self {
+        let tracking_params = [
+            "utm_*", "gclid", "dclid", "fbclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_ga", "_hsenc", "_hsmi",
+        ];
+        Self {
+            tracking_params: tracking_params.iter().map(|p| p.to_string()).collect(),
+            strip_trailing_slash: false,
+            lowercase_path: false,
+        }
+    }
+}
+
+/// Limits on what gets crawled. Empty lists and `None` place no restriction.
+#[derive(Debug, Clone)]
+pub struct CrawlScope {
+    /// Hosts to crawl; each entry also admits its subdomains.
+    pub allowed_hosts: Vec<String>,
+    /// Hosts never to crawl, with their subdomains; these win over `allowed_hosts`.
+    pub denied_hosts: Vec<String>,
+    /// Regexes of which a URL must match at least one.
+    pub include_patterns: Vec<String>,
+    /// Regexes of which a URL must match none.
+    pub exclude_patterns: Vec<String>,
+    /// Links followed from a seed or sitemap, beyond which links are not queued.
+    pub max_depth: Option<u32>,
+    pub max_pages_per_host: Option<usize>,
+    pub max_pages: Option<usize>,
+    /// Response bodies are cut off after this many bytes.
+    pub max_response_bytes: usize,
+}
+
+impl Default for CrawlScope {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            include_patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+            max_depth: None,
+            max_pages_per_host: None,
+            max_pages: None,
+            max_response_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// The shortest and longest time a page may go between revisits.
+#[derive(Debug, Clone, Copy)]
+pub struct RecrawlBounds {
+    pub min: std::time::Duration,
+    pub max: std::time::Duration,
 }
 
 impl Default for CrawlConfig {
@@ -33,6 +145,21 @@ impl Default for CrawlConfig {
             contact_email: "crawler@mysite.com".to_string(),
             default_delay: std::time::Duration::from_secs(2),
             max_concurrent_requests: 10,
+            robots_ttl: std::time::Duration::from_secs(24 * 60 * 60),
+            robots_error_ttl: std::time::Duration::from_secs(10 * 60),
+            max_crawl_delay: std::time::Duration::from_secs(60),
+            state_path: std::path::PathBuf::from("crawl_state.jsonl"),
+            recrawl_interval: Some(std::time::Duration::from_secs(24 * 60 * 60)),
+            recrawl_bounds: RecrawlBounds {
+                min: std::time::Duration::from_secs(60 * 60),
+                max: std::time::Duration::from_secs(30 * 24 * 60 * 60),
+            },
+            site_recrawl_bounds: HashMap::new(),
+            recrawl_budget: Some(1200),
+            scope: CrawlScope::default(),
+            normalization: UrlNormalization::default(),
+            seen_set: SeenSetConfig::Exact,
+            near_duplicates: NearDuplicates { max_distance: 3, follow_links: true },
         }
     }
 }
@@ -1,6 +1,6 @@
 use scraper::{Html, Selector};
-use url::Url;
 use crate::model::CrawledPage;
+use crate::simhash::simhash;
 
 pub struct Parser;
 
@@ -12,25 +12,35 @@ impl Parser {
         let selector = Selector::parse("a[href]").unwrap();
         let mut links = Vec::new();
         for element in document.select(&selector) {
-            if let Some(href) = element.value().attr("href") {
-                if let Ok(url) = page.url.join(href) {
-                    if url.scheme() == "http" || url.scheme() == "https" {
-                        links.push(url);
-                    }
-                }
+            if let Some(href) = element.value().attr("href")
+                && let Ok(url) = page.url.join(href)
+                && (url.scheme() == "http" || url.scheme() == "https")
+            {
+                links.push(url);
             }
         }
         page.outgoing_links = links;
 
+        // The page may name another URL as the one it should be known by
+        let canonical_selector = Selector::parse(r#"link[rel~="canonical"][href]"#).unwrap();
+        page.canonical = document.select(&canonical_selector)
+            .next()
+            .and_then(|element| element.value().attr("href"))
+            .and_then(|href| page.url.join(href.trim()).ok())
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https");
+
         // Extract text content (simplified)
         let text_selector = Selector::parse("body").unwrap();
         if let Some(body) = document.select(&text_selector).next() {
             page.text_content = body.text().collect::<Vec<_>>().join(" ").trim().to_string();
         }
 
-        // Generate content hash for deduplication
+        // Exact content hash, which tells whether a revisited page changed
         let mut context = md5::Context::new();
         context.consume(page.text_content.as_bytes());
         page.content_hash = format!("{:x}", context.compute());
+
+        // ... and a fingerprint that also matches pages differing in a few words
+        page.fingerprint = simhash(&page.text_content);
     }
 }
@@ -1,72 +1,169 @@
-use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use dashmap::DashMap;
 use chrono::{DateTime, Utc};
 use url::Url;
-use robots_txt::Robots;
+use crate::fetcher::Fetcher;
 use crate::model::CrawlConfig;
+use crate::robots::RobotsTxt;
+
+#[derive(Clone)]
+struct CachedRobots {
+    robots: Arc<RobotsTxt>,
+    /// Whether robots.txt could not be fetched (5xx or network error).
+    unavailable: bool,
+    expires: Instant,
+}
+
+/// What robots.txt says about a URL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Allowed,
+    /// A rule of the site's robots.txt forbids the URL.
+    Disallowed,
+    /// robots.txt could not be fetched, so the whole origin is off limits
+    /// until it is tried again at `retry_at`.
+    Unavailable { retry_at: Instant },
+}
 
 pub struct PolitenessManager {
     config: Arc<CrawlConfig>,
-    // Domain -> Robots rules
-    robots_cache: DashMap<String, Arc<Robots<'static>>>,
+    fetcher: Arc<Fetcher>,
+    // Origin (scheme, host, port) -> Robots rules
+    robots_cache: DashMap<String, CachedRobots>,
+    // Origin -> lock held while its robots.txt is fetched, so it is fetched once
+    robots_fetches: DashMap<String, Arc<Mutex<()>>>,
     // Domain -> Last request timestamp
     last_request: DashMap<String, DateTime<Utc>>,
     // Domain -> Active workers (to ensure only one worker hits a domain)
-    active_domains: DashMap<String, Mutex<()>>,
+    active_domains: DashMap<String, Arc<Mutex<()>>>,
 }
 
 impl PolitenessManager {
-    pub fn new(config: Arc<CrawlConfig>) -> Self {
+    pub fn new(config: Arc<CrawlConfig>, fetcher: Arc<Fetcher>) -> Self {
         Self {
             config,
+            fetcher,
             robots_cache: DashMap::new(),
+            robots_fetches: DashMap::new(),
             last_request: DashMap::new(),
             active_domains: DashMap::new(),
         }
     }
 
-    pub async fn can_crawl(&self, url: &Url) -> bool {
-        let domain = match url.domain() {
-            Some(d) => d,
-            None => return false,
-        };
+    pub async fn can_crawl(&self, url: &Url) -> Access {
+        if url.host_str().is_none() {
+            return Access::Disallowed;
+        }
 
-        // 1. Check robots.txt (Simplified - in real life we'd fetch it if not cached)
-        if let Some(robots) = self.robots_cache.get(domain) {
-            if !robots.allowed(self.config.user_agent.as_str(), url.path()) {
-                return false;
-            }
+        let cached = self.robots_for(url).await;
+        if cached.unavailable {
+            return Access::Unavailable { retry_at: cached.expires };
         }
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        if cached.robots.allowed(&self.config.user_agent, &path) { Access::Allowed } else { Access::Disallowed }
+    }
 
-        true
+    /// The sitemaps advertised in the robots.txt of the URL's origin.
+    pub async fn sitemaps_for(&self, url: &Url) -> Vec<Url> {
+        let robots = self.robots_for(url).await.robots;
+        robots.sitemaps().iter().filter_map(|s| url.join(s).ok()).collect()
     }
 
     pub async fn wait_for_politeness(&self, url: &Url) {
-        let domain = match url.domain() {
+        let domain = match url.host_str() {
             Some(d) => d.to_string(),
             None => return,
         };
 
         // Use a per-domain lock to ensure distributed workers don't hit same domain
-        let _lock = self.active_domains.entry(domain.clone()).or_insert_with(|| Mutex::new(())).value().lock().await;
+        let domain_lock = self.active_domains.entry(domain.clone()).or_default().clone();
+        let _lock = domain_lock.lock().await;
+
+        if let Some(last) = self.last_request.get(&domain).map(|t| *t) {
+            let elapsed = Utc::now().signed_duration_since(last);
+            let delay = chrono::Duration::from_std(self.delay_for(url)).unwrap_or(chrono::Duration::seconds(1));
 
-        if let Some(last) = self.last_request.get(&domain) {
-            let elapsed = Utc::now().signed_duration_since(*last);
-            let delay = chrono::Duration::from_std(self.config.default_delay).unwrap_or(chrono::Duration::seconds(1));
-            
             if elapsed < delay {
                 let wait_time = delay - elapsed;
                 tokio::time::sleep(wait_time.to_std().unwrap_or(std::time::Duration::from_secs(1))).await;
             }
         }
-        
+
         self.last_request.insert(domain, Utc::now());
     }
 
-    pub fn update_robots(&self, domain: String, content: &str) {
-        // Implementation for parsing and caching robots.xml content
-        // This is a placeholder for the actual robots-txt crate usage
+    /// The configured delay, raised to the site's `Crawl-delay` (capped at `max_crawl_delay`).
+    pub fn delay_for(&self, url: &Url) -> Duration {
+        let crawl_delay = self.robots_cache.get(&origin_key(url))
+            .and_then(|cached| cached.robots.crawl_delay(&self.config.user_agent))
+            .unwrap_or_default()
+            .min(self.config.max_crawl_delay);
+        self.config.default_delay.max(crawl_delay)
     }
+
+    /// Returns the cached rules for the URL's origin, fetching robots.txt on first
+    /// contact and again once the cached copy expires.
+    async fn robots_for(&self, url: &Url) -> CachedRobots {
+        let key = origin_key(url);
+        if let Some(robots) = self.cached_robots(&key) {
+            return robots;
+        }
+
+        let fetch_lock = self.robots_fetches.entry(key.clone()).or_default().clone();
+        let _fetching = fetch_lock.lock().await;
+        // another worker may have fetched it while we waited
+        if let Some(robots) = self.cached_robots(&key) {
+            return robots;
+        }
+
+        let (robots, ttl) = self.fetch_robots(url).await;
+        let cached = CachedRobots {
+            unavailable: robots.is_none(),
+            robots: Arc::new(robots.unwrap_or_else(RobotsTxt::disallow_all)),
+            expires: Instant::now() + ttl,
+        };
+        self.robots_cache.insert(key, cached.clone());
+        cached
+    }
+
+    fn cached_robots(&self, key: &str) -> Option<CachedRobots> {
+        self.robots_cache.get(key)
+            .filter(|cached| cached.expires > Instant::now())
+            .map(|cached| cached.clone())
+    }
+
+    /// Applies the RFC 9309 status rules: a 2xx body is parsed, a 4xx means there
+    /// are no restrictions, and a 5xx or network error (`None`) disallows
+    /// everything until the shorter `robots_error_ttl` has passed and the file
+    /// is tried again.
+    async fn fetch_robots(&self, url: &Url) -> (Option<RobotsTxt>, Duration) {
+        let Ok(robots_url) = url.join("/robots.txt") else {
+            return (Some(RobotsTxt::allow_all()), self.config.robots_ttl);
+        };
+        match self.fetcher.fetch_robots(&robots_url).await {
+            Ok((status, body)) if (200..300).contains(&status) => {
+                (Some(RobotsTxt::parse(&body)), self.config.robots_ttl)
+            }
+            // 4xx, or redirects that went nowhere
+            Ok((status, _)) if status < 500 => (Some(RobotsTxt::allow_all()), self.config.robots_ttl),
+            Ok((status, _)) => {
+                eprintln!("robots.txt at {} returned {}, disallowing for now", robots_url, status);
+                (None, self.config.robots_error_ttl)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch {}: {}, disallowing for now", robots_url, e);
+                (None, self.config.robots_error_ttl)
+            }
+        }
+    }
+}
+
+/// robots.txt applies per scheme, host and port.
+fn origin_key(url: &Url) -> String {
+    url.origin().ascii_serialization()
 }
@@ -0,0 +1,197 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use url::Url;
+use crate::model::{CrawlConfig, RecrawlBounds};
+use crate::store::{url_key, ChangeHistory, UrlKey};
+
+/// A page seen to change is revisited this much sooner next time...
+const SPEED_UP: f64 = 0.5;
+/// ...and one found unchanged this much later.
+const BACK_OFF: f64 = 1.5;
+
+/// Whether a revisit found the page as it was last time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freshness {
+    /// A 304, or a 200 whose content hash matches the stored one.
+    Unchanged,
+    Changed,
+}
+
+/// Pages by the time they come due, earliest on top.
+type DueQueue = BinaryHeap<Reverse<(DateTime<Utc>, UrlKey)>>;
+
+struct Schedule {
+    due: DueQueue,
+    /// Earliest time the revisit budget allows the next revisit.
+    next_slot: DateTime<Utc>,
+}
+
+/// Decides when crawled pages are visited again. Each page has its own interval,
+/// halved when a revisit finds it changed and stretched when it has not, within
+/// its site's bounds. Due pages are handed out earliest first, no faster than
+/// the hourly revisit budget allows.
+pub struct RecrawlScheduler {
+    config: Arc<CrawlConfig>,
+    initial: Duration,
+    schedule: Mutex<Schedule>,
+    recrawled: AtomicU64,
+    unchanged: AtomicU64,
+}
+
+impl RecrawlScheduler {
+    pub fn new(config: Arc<CrawlConfig>, initial: Duration) -> Self {
+        Self {
+            config,
+            initial,
+            schedule: Mutex::new(Schedule { due: BinaryHeap::new(), next_slot: Utc::now() }),
+            recrawled: AtomicU64::new(0),
+            unchanged: AtomicU64::new(0),
+        }
+    }
+
+    /// The interval a page is on: the one chosen at its last revisit, or the
+    /// initial interval if it has not been revisited yet.
+    pub fn interval(&self, url: &Url, history: &ChangeHistory) -> Duration {
+        let interval = history.interval_secs.map(Duration::from_secs).unwrap_or(self.initial);
+        self.clamp(url, interval)
+    }
+
+    /// The interval to use after a revisit found the page in `freshness`.
+    pub fn adapt(&self, url: &Url, current: Duration, freshness: Freshness) -> Duration {
+        let factor = match freshness {
+            Freshness::Changed => SPEED_UP,
+            Freshness::Unchanged => BACK_OFF,
+        };
+        self.clamp(url, current.mul_f64(factor))
+    }
+
+    pub fn schedule(&self, url: &Url, last_visit: DateTime<Utc>, interval: Duration) {
+        let interval = chrono::Duration::from_std(interval).unwrap_or(chrono::Duration::days(1));
+        self.lock().due.push(Reverse((last_visit + interval, url_key(url))));
+    }
+
+    /// Takes the page that has been due the longest, if any is due now and the
+    /// budget has room for it. Pages are held by key; the crawl store has the URL.
+    pub fn next_due(&self) -> Option<UrlKey> {
+        let now = Utc::now();
+        let mut schedule = self.lock();
+        if schedule.next_slot > now {
+            return None;
+        }
+        match schedule.due.peek() {
+            Some(Reverse((at, _))) if *at <= now => {
+                schedule.next_slot = now + self.spacing();
+                schedule.due.pop().map(|Reverse((_, key))| key)
+            }
+            _ => None,
+        }
+    }
+
+    /// How long until a page can next be revisited, or `None` if nothing is scheduled.
+    pub fn time_until_next(&self) -> Option<Duration> {
+        let schedule = self.lock();
+        let Reverse((at, _)) = schedule.due.peek()?;
+        Some(((*at).max(schedule.next_slot) - Utc::now()).to_std().unwrap_or_default())
+    }
+
+    /// Counts the outcome of a revisit and returns (revisits, unchanged) so far.
+    pub fn record(&self, freshness: Freshness) -> (u64, u64) {
+        let recrawled = self.recrawled.fetch_add(1, Ordering::Relaxed) + 1;
+        let unchanged = match freshness {
+            Freshness::Unchanged => self.unchanged.fetch_add(1, Ordering::Relaxed) + 1,
+            Freshness::Changed => self.unchanged.load(Ordering::Relaxed),
+        };
+        (recrawled, unchanged)
+    }
+
+    fn clamp(&self, url: &Url, interval: Duration) -> Duration {
+        let bounds = url.host_str()
+            .and_then(|host| self.config.site_recrawl_bounds.get(host))
+            .unwrap_or(&self.config.recrawl_bounds);
+        clamp_to(bounds, interval)
+    }
+
+    /// The gap between revisits that keeps them within the hourly budget.
+    fn spacing(&self) -> chrono::Duration {
+        match self.config.recrawl_budget {
+            Some(per_hour) if per_hour > 0 => chrono::Duration::milliseconds(3_600_000 / i64::from(per_hour)),
+            _ => chrono::Duration::zero(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Schedule> {
+        self.schedule.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn clamp_to(bounds: &RecrawlBounds, interval: Duration) -> Duration {
+    // not Duration::clamp, which panics if the bounds are misconfigured the wrong way round
+    interval.min(bounds.max).max(bounds.min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn scheduler(config: CrawlConfig) -> RecrawlScheduler {
+        RecrawlScheduler::new(Arc::new(config), 24 * HOUR)
+    }
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn site_bounds_override_the_default_bounds() {
+        let mut config = CrawlConfig {
+            recrawl_bounds: RecrawlBounds { min: 2 * HOUR, max: 48 * HOUR },
+            ..CrawlConfig::default()
+        };
+        config.site_recrawl_bounds.insert("news.example.com".to_string(), RecrawlBounds { min: HOUR / 6, max: 2 * HOUR });
+        let scheduler = scheduler(config);
+        let news = url("https://news.example.com/today");
+        let docs = url("https://docs.example.com/");
+
+        assert_eq!(scheduler.interval(&news, &ChangeHistory::default()), 2 * HOUR);
+        assert_eq!(scheduler.interval(&docs, &ChangeHistory::default()), 24 * HOUR);
+        assert_eq!(scheduler.adapt(&news, HOUR / 4, Freshness::Changed), HOUR / 6);
+        assert_eq!(scheduler.adapt(&docs, 40 * HOUR, Freshness::Unchanged), 48 * HOUR);
+        assert_eq!(scheduler.adapt(&docs, 3 * HOUR, Freshness::Changed), 2 * HOUR);
+    }
+
+    #[test]
+    fn budget_spaces_out_due_pages() {
+        let scheduler = scheduler(CrawlConfig {
+            recrawl_bounds: RecrawlBounds { min: Duration::ZERO, max: 48 * HOUR },
+            recrawl_budget: Some(3600),
+            ..CrawlConfig::default()
+        });
+        let (a, b) = (url("https://example.com/a"), url("https://example.com/b"));
+        let long_ago = Utc::now() - chrono::Duration::days(2);
+        scheduler.schedule(&b, long_ago, HOUR);
+        scheduler.schedule(&a, long_ago, Duration::ZERO);
+
+        // earliest due first, then nothing until the budget's next slot a second later
+        assert_eq!(scheduler.next_due(), Some(url_key(&a)));
+        assert_eq!(scheduler.next_due(), None);
+        let wait = scheduler.time_until_next().unwrap();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1), "waited {wait:?}");
+    }
+
+    #[test]
+    fn no_budget_hands_out_every_due_page() {
+        let scheduler = scheduler(CrawlConfig { recrawl_budget: None, ..CrawlConfig::default() });
+        let long_ago = Utc::now() - chrono::Duration::days(2);
+        scheduler.schedule(&url("https://example.com/a"), long_ago, HOUR);
+        scheduler.schedule(&url("https://example.com/b"), long_ago, HOUR);
+        assert!(scheduler.next_due().is_some());
+        assert!(scheduler.next_due().is_some());
+        assert_eq!(scheduler.next_due(), None);
+    }
+}
@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+/// Only this much of a robots.txt file is parsed (RFC 9309 section 2.5).
+pub const MAX_ROBOTS_BYTES: usize = 500 * 1024;
+
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+/// A parsed robots.txt file following RFC 9309, plus the non-standard `Crawl-delay`.
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    groups: Vec<Group>,
+    sitemaps: Vec<String>,
+}
+
+impl RobotsTxt {
+    /// Permits everything; used when the file is missing (4xx).
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Forbids everything; used while the file is unreachable (5xx or network errors).
+    pub fn disallow_all() -> Self {
+        Self {
+            groups: vec![Group {
+                agents: vec!["*".to_string()],
+                rules: vec![Rule { allow: false, pattern: "/".to_string() }],
+                crawl_delay: None,
+            }],
+            sitemaps: Vec::new(),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut content = content;
+        if content.len() > MAX_ROBOTS_BYTES {
+            let mut end = MAX_ROBOTS_BYTES;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            content = &content[..end];
+        }
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut sitemaps = Vec::new();
+        // a user-agent line directly after another one extends the same group
+        let mut in_agent_lines = false;
+
+        for line in content.trim_start_matches('\u{feff}').lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agent_lines || groups.is_empty() {
+                        groups.push(Group::default());
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(product_token(value));
+                    }
+                    in_agent_lines = true;
+                }
+                directive @ ("allow" | "disallow") => {
+                    in_agent_lines = false;
+                    // rules before the first user-agent line belong to no group
+                    let Some(group) = groups.last_mut() else { continue };
+                    // an empty pattern matches nothing
+                    if value.is_empty() {
+                        continue;
+                    }
+                    group.rules.push(Rule { allow: directive == "allow", pattern: encode_pattern(value) });
+                }
+                "crawl-delay" => {
+                    in_agent_lines = false;
+                    if let Some(group) = groups.last_mut()
+                        && let Ok(secs) = value.parse::<f64>()
+                        && secs.is_finite() && secs >= 0.0
+                    {
+                        group.crawl_delay = Some(Duration::from_secs_f64(secs));
+                    }
+                }
+                // sitemaps apply to the whole file, not to a group
+                "sitemap" if !value.is_empty() => sitemaps.push(value.to_string()),
+                _ => {
+                    // other records do not end a run of user-agent lines
+                }
+            }
+        }
+
+        Self { groups, sitemaps }
+    }
+
+    /// Whether `user_agent` may fetch `path` (path plus optional query, percent-encoded).
+    pub fn allowed(&self, user_agent: &str, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+        let mut best: Option<&Rule> = None;
+        for group in self.groups_for(user_agent) {
+            for rule in &group.rules {
+                if !pattern_matches(&rule.pattern, path) {
+                    continue;
+                }
+                // the longest match wins; on a tie allow beats disallow
+                let better = match best {
+                    None => true,
+                    Some(b) => rule.pattern.len() > b.pattern.len()
+                        || (rule.pattern.len() == b.pattern.len() && rule.allow && !b.allow),
+                };
+                if better {
+                    best = Some(rule);
+                }
+            }
+        }
+        best.is_none_or(|rule| rule.allow)
+    }
+
+    /// The `Crawl-delay` of the group that applies to `user_agent`, if any.
+    pub fn crawl_delay(&self, user_agent: &str) -> Option<Duration> {
+        self.groups_for(user_agent).filter_map(|g| g.crawl_delay).max()
+    }
+
+    /// The `Sitemap:` URLs listed in the file, as written.
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+
+    /// All groups naming `user_agent`, or the `*` groups if none does.
+    /// Several groups for the same agent are combined.
+    fn groups_for<'a>(&'a self, user_agent: &str) -> impl Iterator<Item = &'a Group> {
+        let token = product_token(user_agent);
+        let named = self.groups.iter().any(|g| g.agents.contains(&token));
+        let wanted = if named { token } else { "*".to_string() };
+        self.groups.iter().filter(move |g| g.agents.contains(&wanted))
+    }
+}
+
+/// Reduces a user-agent string like `MySearchBot/1.0 (+https://..)` to its
+/// lowercase product token `mysearchbot`.
+fn product_token(user_agent: &str) -> String {
+    user_agent.trim()
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// Percent-encodes non-ASCII characters so patterns compare against encoded URL paths.
+fn encode_pattern(pattern: &str) -> String {
+    let mut encoded = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        if c.is_ascii() {
+            encoded.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    encoded
+}
+
+/// Matches a rule pattern against a path: `*` matches any sequence of
+/// characters and a trailing `$` anchors the pattern at the end of the path.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let mut pieces = pattern.split('*');
+    let first = pieces.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else { return false };
+
+    let pieces: Vec<&str> = pieces.collect();
+    for (i, piece) in pieces.iter().enumerate() {
+        let last = i == pieces.len() - 1;
+        if last && anchored {
+            return rest.ends_with(piece);
+        }
+        match rest.find(piece) {
+            Some(pos) => rest = &rest[pos + piece.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENT: &str = "MySearchBot/1.0";
+
+    /// Checks each `(path, allowed)` against `robots` for `user_agent`.
+    fn check(robots: &str, user_agent: &str, cases: &[(&str, bool)]) {
+        let robots = RobotsTxt::parse(robots);
+        for &(path, expected) in cases {
+            assert_eq!(robots.allowed(user_agent, path), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn longest_match_wins_and_allow_wins_ties() {
+        let robots = "User-agent: *\nDisallow: /folder/\nAllow: /folder/page\nDisallow: /tie\nAllow: /tie\nAllow: /a\nDisallow: /a/b\n";
+        check(robots, AGENT, &[
+            ("/folder/page.html", true),
+            ("/folder/other.html", false),
+            ("/folder", true),
+            ("/tie/x", true),
+            ("/a/c", true),
+            ("/a/b/c", false),
+            ("/robots.txt", true),
+        ]);
+    }
+
+    #[test]
+    fn wildcards() {
+        let robots = "User-agent: *\nDisallow: /*.php$\nDisallow: /private*/\nDisallow: /*?sessionid=\nDisallow: /exact$\n";
+        check(robots, AGENT, &[
+            ("/index.php", false),
+            ("/a/b.php", false),
+            ("/index.php?x=1", true),
+            ("/index.phps", true),
+            ("/private-stuff/x", false),
+            ("/private/", false),
+            ("/private", true),
+            ("/page?sessionid=42", false),
+            ("/page?id=42", true),
+            ("/exact", false),
+            ("/exact/more", true),
+        ]);
+    }
+
+    #[test]
+    fn percent_encoding() {
+        // non-ASCII patterns are compared in their encoded form, as paths arrive
+        let robots = "User-agent: *\nDisallow: /café\nDisallow: /a%20b\n";
+        check(robots, AGENT, &[
+            ("/caf%C3%A9/menu", false),
+            ("/cafe", true),
+            ("/a%20b", false),
+            ("/a b", true),
+        ]);
+    }
+
+    #[test]
+    fn group_selection_by_product_token() {
+        let robots = "\
+User-agent: *
+Disallow: /
+
+User-agent: OtherBot
+User-agent: mysearchbot
+Disallow: /private
+
+User-agent: MySearchBot
+Disallow: /tmp
+";
+        // groups naming the bot are combined, and the `*` group no longer applies
+        check(robots, AGENT, &[("/", true), ("/private/x", false), ("/tmp/x", false)]);
+        check(robots, "otherbot", &[("/tmp/x", true), ("/private/x", false)]);
+        // an agent no group names falls back to `*`
+        check(robots, "UnknownBot/2.0 (+https://example.com)", &[("/", false), ("/page", false)]);
+        // and with no `*` group, to no rules at all
+        check("User-agent: OtherBot\nDisallow: /\n", AGENT, &[("/page", true)]);
+    }
+
+    #[test]
+    fn empty_disallow_allows_everything() {
+        check("User-agent: *\nDisallow:\n", AGENT, &[("/", true), ("/any/page", true)]);
+        check("User-agent: mysearchbot\nDisallow:\n\nUser-agent: *\nDisallow: /\n", AGENT, &[("/page", true)]);
+    }
+}
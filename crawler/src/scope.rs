@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use url::Url;
+use crate::model::CrawlScope;
+
+/// A `CrawlScope` with its patterns compiled, deciding which URLs may be queued.
+/// Page budgets are counted by the frontier, which knows what it has admitted.
+pub struct Scope {
+    allowed_hosts: Vec<String>,
+    denied_hosts: Vec<String>,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    max_depth: Option<u32>,
+    pub max_pages_per_host: Option<usize>,
+    pub max_pages: Option<usize>,
+}
+
+impl Scope {
+    pub fn new(config: &CrawlScope) -> Result<Self> {
+        Ok(Self {
+            allowed_hosts: normalize_hosts(&config.allowed_hosts),
+            denied_hosts: normalize_hosts(&config.denied_hosts),
+            include: compile(&config.include_patterns)?,
+            exclude: compile(&config.exclude_patterns)?,
+            max_depth: config.max_depth,
+            max_pages_per_host: config.max_pages_per_host,
+            max_pages: config.max_pages,
+        })
+    }
+
+    /// Whether `url`, reached `depth` links from a seed, is inside the scope.
+    pub fn allows(&self, url: &Url, depth: u32) -> bool {
+        let Some(host) = url.host_str() else { return false };
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return false;
+        }
+        if self.denied_hosts.iter().any(|h| host_matches(host, h)) {
+            return false;
+        }
+        if !self.allowed_hosts.is_empty() && !self.allowed_hosts.iter().any(|h| host_matches(host, h)) {
+            return false;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|r| r.is_match(url.as_str())) {
+            return false;
+        }
+        !self.exclude.iter().any(|r| r.is_match(url.as_str()))
+    }
+}
+
+fn normalize_hosts(hosts: &[String]) -> Vec<String> {
+    hosts.iter().map(|h| h.trim().trim_end_matches('.').to_ascii_lowercase()).collect()
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns.iter()
+        .map(|p| Regex::new(p).with_context(|| format!("invalid scope pattern {:?}", p)))
+        .collect()
+}
+
+/// `docs.example.com` matches itself and `api.docs.example.com`, but not `olddocs.example.com`.
+fn host_matches(host: &str, pattern: &str) -> bool {
+    host.strip_suffix(pattern).is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('.'))
+}
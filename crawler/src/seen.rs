@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use anyhow::{bail, Result};
+use url::Url;
+use crate::model::SeenSetConfig;
+
+/// Remembers which URLs the frontier has already queued.
+pub trait SeenSet: Send {
+    /// Records `url`, returning whether it was new.
+    fn insert(&mut self, url: &Url) -> bool;
+    fn contains(&self, url: &Url) -> bool;
+}
+
+pub fn from_config(config: SeenSetConfig) -> Result<Box<dyn SeenSet>> {
+    Ok(match config {
+        SeenSetConfig::Exact => Box::new(ExactSeenSet::default()),
+        SeenSetConfig::Bloom { initial_capacity, false_positive_rate } => {
+            Box::new(ScalableBloomFilter::new(initial_capacity, false_positive_rate)?)
+        }
+    })
+}
+
+/// Keeps every URL; exact, but memory grows with the crawl.
+#[derive(Default)]
+pub struct ExactSeenSet {
+    urls: HashSet<Url>,
+}
+
+impl SeenSet for ExactSeenSet {
+    fn insert(&mut self, url: &Url) -> bool {
+        self.urls.insert(url.clone())
+    }
+
+    fn contains(&self, url: &Url) -> bool {
+        self.urls.contains(url)
+    }
+}
+
+/// Each filter added holds this many times more URLs than the one before...
+const GROWTH: usize = 2;
+/// ...with this fraction of its false-positive rate, so the rates sum to at most the target.
+const TIGHTENING: f64 = 0.85;
+
+/// A scalable Bloom filter (Almeida et al., 2007): a chain of Bloom filters
+/// where a new, larger and stricter one is started whenever the newest fills
+/// up. Memory grows with the URLs seen, at two or three bytes per URL rather
+/// than the URL itself, and the share of new URLs wrongly reported as seen
+/// stays below the configured rate however many URLs arrive.
+pub struct ScalableBloomFilter {
+    filters: Vec<BloomFilter>,
+    initial_capacity: usize,
+    false_positive_rate: f64,
+}
+
+impl ScalableBloomFilter {
+    /// Fails unless `false_positive_rate` is strictly between 0 and 1.
+    pub fn new(initial_capacity: usize, false_positive_rate: f64) -> Result<Self> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            bail!("false-positive rate must be between 0 and 1, got {}", false_positive_rate);
+        }
+        let mut filter = Self { filters: Vec::new(), initial_capacity: initial_capacity.max(1), false_positive_rate };
+        filter.grow();
+        Ok(filter)
+    }
+
+    fn grow(&mut self) {
+        let i = self.filters.len() as u32;
+        let capacity = self.initial_capacity.saturating_mul(GROWTH.saturating_pow(i));
+        let rate = self.false_positive_rate * (1.0 - TIGHTENING) * TIGHTENING.powi(i as i32);
+        self.filters.push(BloomFilter::new(capacity, rate));
+    }
+}
+
+impl SeenSet for ScalableBloomFilter {
+    fn insert(&mut self, url: &Url) -> bool {
+        let hashes = Hashes::of(url);
+        if self.filters.iter().any(|f| f.contains(hashes)) {
+            return false;
+        }
+        if self.filters.last().is_none_or(|f| f.is_full()) {
+            self.grow();
+        }
+        if let Some(filter) = self.filters.last_mut() {
+            filter.insert(hashes);
+        }
+        true
+    }
+
+    fn contains(&self, url: &Url) -> bool {
+        let hashes = Hashes::of(url);
+        self.filters.iter().any(|f| f.contains(hashes))
+    }
+}
+
+/// Two independent 64-bit hashes, combined as `h1 + i * h2` for the i-th probe
+/// (Kirsch and Mitzenmacher), so each URL is hashed once however many probes there are.
+#[derive(Clone, Copy)]
+struct Hashes(u64, u64);
+
+impl Hashes {
+    fn of(url: &Url) -> Self {
+        let digest = md5::compute(url.as_str()).0;
+        let (a, b) = digest.split_at(8);
+        Self(u64::from_le_bytes(a.try_into().unwrap_or_default()), u64::from_le_bytes(b.try_into().unwrap_or_default()))
+    }
+
+    fn probe(self, i: u64, bits: u64) -> u64 {
+        self.0.wrapping_add(i.wrapping_mul(self.1)) % bits
+    }
+}
+
+struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u64,
+    capacity: usize,
+    len: usize,
+}
+
+impl BloomFilter {
+    /// Sized for `capacity` URLs at `rate` false positives.
+    fn new(capacity: usize, rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round().max(1.0) as u64;
+        Self { bits: vec![0; num_bits.div_ceil(64) as usize], num_bits, num_hashes, capacity, len: 0 }
+    }
+
+    fn is_full(&self) -> bool {
+        self.len >= self.capacity
+    }
+
+    fn insert(&mut self, hashes: Hashes) {
+        for i in 0..self.num_hashes {
+            let bit = hashes.probe(i, self.num_bits);
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+
+    fn contains(&self, hashes: Hashes) -> bool {
+        (0..self.num_hashes).all(|i| {
+            let bit = hashes.probe(i, self.num_bits);
+            self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+}
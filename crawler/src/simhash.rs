@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use url::Url;
+use crate::store::{url_key, UrlKey};
+
+/// Pages with less text than this are too short to fingerprint meaningfully;
+/// nearly all their words would be navigation shared with other pages.
+const MIN_WORDS: usize = 20;
+/// The index keeps one table per block, and needs `max_distance + 1` blocks.
+const MAX_BLOCKS: u32 = 8;
+
+/// The 64-bit SimHash (Charikar, 2002) of a page's text, with its words as
+/// features weighted by how often they occur, or `None` if the text is too
+/// short. Pages that differ only in a timestamp, a counter or a few lines of
+/// chrome get fingerprints a few bits apart. Word shingles would also capture
+/// word order, but spread each edit over several features and push such pages
+/// past any useful threshold.
+pub fn simhash(text: &str) -> Option<u64> {
+    let words: Vec<String> = text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+
+    let mut weights = [0i32; 64];
+    for word in &words {
+        let hash = fnv1a(word.as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+    Some(weights.iter().enumerate().filter(|(_, w)| **w > 0).fold(0, |fp, (bit, _)| fp | 1 << bit))
+}
+
+/// FNV-1a, so fingerprints stored by one build still match those of the next.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+struct Tables {
+    /// One table per block of fingerprint bits: block value -> fingerprints with it.
+    blocks: Vec<HashMap<u64, Vec<(u64, UrlKey)>>>,
+    /// Each indexed page, with the fingerprint it is indexed under.
+    pages: HashMap<UrlKey, (u64, Url)>,
+}
+
+/// Fingerprints of the distinct pages crawled so far. Two fingerprints at most
+/// `max_distance` bits apart agree exactly on at least one of `max_distance + 1`
+/// blocks (Manku et al., 2007), so only pages sharing a block are compared.
+pub struct FingerprintIndex {
+    max_distance: u32,
+    tables: Mutex<Tables>,
+}
+
+impl FingerprintIndex {
+    pub fn new(max_distance: u32) -> Self {
+        let max_distance = max_distance.min(MAX_BLOCKS - 1);
+        let blocks = (0..=max_distance).map(|_| HashMap::new()).collect();
+        Self { max_distance, tables: Mutex::new(Tables { blocks, pages: HashMap::new() }) }
+    }
+
+    /// Returns the page `url` nearly duplicates, if any. Otherwise `url` is
+    /// indexed under `fingerprint`, replacing what it was indexed under before.
+    /// Duplicates are not indexed, so a chain of small edits cannot drift away
+    /// from the original one duplicate at a time.
+    pub fn insert(&self, url: &Url, fingerprint: u64) -> Option<Url> {
+        let key = url_key(url);
+        let mut tables = self.tables.lock().unwrap_or_else(|e| e.into_inner());
+        let blocks = tables.blocks.len() as u32;
+        if let Some((old, _)) = tables.pages.remove(&key) {
+            for (i, table) in tables.blocks.iter_mut().enumerate() {
+                if let Some(entries) = table.get_mut(&block(old, i as u32, blocks)) {
+                    entries.retain(|(_, k)| *k != key);
+                }
+            }
+        }
+
+        let original = tables.blocks.iter().enumerate()
+            .filter_map(|(i, table)| table.get(&block(fingerprint, i as u32, blocks)))
+            .flatten()
+            .filter(|(fp, _)| (fp ^ fingerprint).count_ones() <= self.max_distance)
+            .min_by_key(|(fp, _)| (fp ^ fingerprint).count_ones())
+            .and_then(|(_, k)| tables.pages.get(k))
+            .map(|(_, u)| u.clone());
+        if original.is_some() {
+            return original;
+        }
+
+        for (i, table) in tables.blocks.iter_mut().enumerate() {
+            table.entry(block(fingerprint, i as u32, blocks)).or_default().push((fingerprint, key));
+        }
+        tables.pages.insert(key, (fingerprint, url.clone()));
+        None
+    }
+}
+
+/// The `i`-th of `blocks` roughly equal runs of bits in `fingerprint`.
+fn block(fingerprint: u64, i: u32, blocks: u32) -> u64 {
+    let start = i * 64 / blocks;
+    let end = (i + 1) * 64 / blocks;
+    let width = end - start;
+    let mask = if width == 64 { u64::MAX } else { (1 << width) - 1 };
+    fingerprint >> start & mask
+}
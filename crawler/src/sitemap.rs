@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+use anyhow::{Result, bail};
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use url::Url;
+use xml::reader::{EventReader, XmlEvent};
+use crate::fetcher::Fetcher;
+use crate::politeness::{Access, PolitenessManager};
+
+/// Protocol limits: a sitemap holds at most 50,000 URLs and 50 MB uncompressed.
+pub const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+const MAX_SITEMAP_URLS: usize = 50_000;
+/// Stops a site with thousands of sitemap files from stalling the seeding phase.
+const MAX_SITEMAPS_PER_SITE: usize = 1000;
+/// Indexes may not nest per the protocol, but one level of nesting is tolerated.
+const MAX_INDEX_DEPTH: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct SitemapEntry {
+    pub url: Url,
+    pub lastmod: Option<DateTime<Utc>>,
+    pub priority: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum Sitemap {
+    /// A `<sitemapindex>` listing further sitemaps.
+    Index(Vec<Url>),
+    /// A `<urlset>` listing pages.
+    UrlSet(Vec<SitemapEntry>),
+}
+
+/// Parses a sitemap or sitemap index, gunzipping it first if needed.
+pub fn parse(body: &[u8]) -> Result<Sitemap> {
+    let body = decompress(body)?;
+    let reader = EventReader::new(body.as_ref());
+
+    let mut root: Option<String> = None;
+    let mut field: Option<String> = None;
+    let mut text = String::new();
+    let mut loc: Option<Url> = None;
+    let mut lastmod = None;
+    let mut priority = None;
+    let mut sitemaps = Vec::new();
+    let mut entries = Vec::new();
+
+    for event in reader {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                let name = name.local_name;
+                if root.is_none() {
+                    if name != "urlset" && name != "sitemapindex" {
+                        bail!("not a sitemap: root element is <{}>", name);
+                    }
+                    root = Some(name);
+                } else if matches!(name.as_str(), "loc" | "lastmod" | "priority") {
+                    field = Some(name);
+                    text.clear();
+                }
+            }
+            XmlEvent::Characters(s) | XmlEvent::CData(s) if field.is_some() => text.push_str(&s),
+            XmlEvent::EndElement { name } => {
+                match name.local_name.as_str() {
+                    "loc" => loc = Url::parse(text.trim()).ok().filter(|u| matches!(u.scheme(), "http" | "https")),
+                    "lastmod" => lastmod = parse_lastmod(text.trim()),
+                    "priority" => priority = text.trim().parse::<f32>().ok().filter(|p| (0.0..=1.0).contains(p)),
+                    "sitemap" => {
+                        sitemaps.extend(loc.take());
+                        lastmod = None;
+                    }
+                    "url" => {
+                        if let Some(url) = loc.take() {
+                            entries.push(SitemapEntry { url, lastmod: lastmod.take(), priority: priority.take() });
+                        }
+                        lastmod = None;
+                        priority = None;
+                        if entries.len() >= MAX_SITEMAP_URLS {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                field = None;
+            }
+            _ => {}
+        }
+        if text.len() > 4096 {
+            bail!("sitemap field too long");
+        }
+    }
+
+    match root.as_deref() {
+        Some("sitemapindex") => Ok(Sitemap::Index(sitemaps)),
+        Some(_) => Ok(Sitemap::UrlSet(entries)),
+        None => bail!("empty sitemap"),
+    }
+}
+
+/// Sitemaps may be served as `.xml.gz`; detect gzip by its magic bytes rather
+/// than trusting the URL or the Content-Type.
+fn decompress(body: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if !body.starts_with(&[0x1f, 0x8b]) {
+        return Ok(Cow::Borrowed(body));
+    }
+    let mut out = Vec::new();
+    GzDecoder::new(body).take(MAX_SITEMAP_BYTES as u64 + 1).read_to_end(&mut out)?;
+    if out.len() > MAX_SITEMAP_BYTES {
+        bail!("sitemap exceeds {} bytes uncompressed", MAX_SITEMAP_BYTES);
+    }
+    Ok(Cow::Owned(out))
+}
+
+/// Parses the W3C Datetime formats allowed in `<lastmod>`: a full timestamp,
+/// one without seconds, or a bare date.
+fn parse_lastmod(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Some(t.with_timezone(&Utc));
+    }
+    if let Ok(t) = DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M%:z") {
+        return Some(t.with_timezone(&Utc));
+    }
+    if let Some(t) = s.strip_suffix('Z')
+        && let Ok(t) = chrono::NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M")
+    {
+        return Some(t.and_utc());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0).map(|t| t.and_utc())
+}
+
+/// Collects page URLs from the sitemaps of `seed`'s site: those listed in its
+/// robots.txt plus `/sitemap.xml`, following sitemap indexes.
+pub async fn discover(seed: &Url, fetcher: &Fetcher, politeness: &PolitenessManager) -> Vec<SitemapEntry> {
+    let advertised = politeness.sitemaps_for(seed).await;
+    let mut queue: VecDeque<(Url, usize)> = advertised.iter().map(|u| (u.clone(), 0)).collect();
+    if let Ok(default) = seed.join("/sitemap.xml") {
+        queue.push_back((default, 0));
+    }
+
+    let mut visited = HashSet::new();
+    let mut entries = Vec::new();
+    while let Some((url, depth)) = queue.pop_front() {
+        if visited.len() >= MAX_SITEMAPS_PER_SITE {
+            eprintln!("Stopping after {} sitemaps for {}", MAX_SITEMAPS_PER_SITE, seed);
+            break;
+        }
+        if !visited.insert(url.clone()) {
+            continue;
+        }
+        // sitemaps named in robots.txt were offered explicitly; others must be allowed
+        if !advertised.contains(&url) && politeness.can_crawl(&url).await != Access::Allowed {
+            continue;
+        }
+        politeness.wait_for_politeness(&url).await;
+
+        let body = match fetcher.fetch_bytes(&url, MAX_SITEMAP_BYTES).await {
+            Ok((status, body)) if (200..300).contains(&status) => body,
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("Failed to fetch sitemap {}: {}", url, e);
+                continue;
+            }
+        };
+        match parse(&body) {
+            Ok(Sitemap::Index(sitemaps)) if depth < MAX_INDEX_DEPTH => {
+                println!("Sitemap index {} lists {} sitemaps", url, sitemaps.len());
+                queue.extend(sitemaps.into_iter().map(|u| (u, depth + 1)));
+            }
+            Ok(Sitemap::Index(_)) => eprintln!("Ignoring nested sitemap index {}", url),
+            Ok(Sitemap::UrlSet(urls)) => {
+                println!("Sitemap {} lists {} URLs", url, urls.len());
+                entries.extend(urls);
+            }
+            Err(e) => eprintln!("Failed to parse sitemap {}: {}", url, e),
+        }
+    }
+    entries
+}
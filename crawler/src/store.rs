@@ -0,0 +1,667 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use crate::frontier::{self, FrontierEntry};
+use crate::model::CrawledPage;
+use crate::seen::SeenSet;
+
+/// The outcome of the most recent attempt to crawl a URL.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlStatus {
+    Fetched,
+    NotModified,
+    Failed,
+    Skipped,
+}
+
+/// Identifies a URL in memory: its MD5, so the state index and the revisit
+/// schedule hold 16 bytes per URL rather than the URL itself.
+pub type UrlKey = [u8; 16];
+
+pub fn url_key(url: &Url) -> UrlKey {
+    md5::compute(url.as_str()).0
+}
+
+/// What is known about a URL from earlier crawls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlState {
+    pub status: CrawlStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// SimHash of the page text, if there was enough text to take one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<u64>,
+    /// The page this one nearly duplicates; such pages are not indexed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<Url>,
+    /// The other URL the page names as canonical; such pages are not revisited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<Url>,
+    pub updated: DateTime<Utc>,
+    #[serde(default)]
+    pub history: ChangeHistory,
+    /// Links followed from a seed or sitemap to reach the URL, kept so links
+    /// found on a revisit are as deep as those found on the first visit.
+    #[serde(default)]
+    pub depth: u32,
+}
+
+impl UrlState {
+    /// Whether the page is one to revisit: robots.txt does not disallow it,
+    /// and it names no other URL as canonical.
+    pub fn revisitable(&self) -> bool {
+        self.status != CrawlStatus::Skipped && self.canonical.is_none()
+    }
+}
+
+/// What revisits of a URL have found so far.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChangeHistory {
+    pub revisits: u32,
+    pub changes: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_changed: Option<DateTime<Utc>>,
+    /// Seconds between visits, as chosen after the latest revisit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+}
+
+/// One line of the crawl log.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Enqueued {
+        url: Url,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lastmod: Option<DateTime<Utc>>,
+        priority: f32,
+        #[serde(default)]
+        depth: u32,
+    },
+    Fetched {
+        url: Url,
+        http_status: u16,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        content_hash: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fingerprint: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duplicate_of: Option<Url>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        canonical: Option<Url>,
+        #[serde(default)]
+        depth: u32,
+        at: DateTime<Utc>,
+    },
+    NotModified { url: Url, at: DateTime<Utc> },
+    Failed {
+        url: Url,
+        error: String,
+        /// The status of the response, if the server sent an error page.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        http_status: Option<u16>,
+        /// Seconds until the page is tried again, if it is revisited.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval_secs: Option<u64>,
+        #[serde(default)]
+        depth: u32,
+        at: DateTime<Utc>,
+    },
+    Skipped {
+        url: Url,
+        reason: String,
+        #[serde(default)]
+        depth: u32,
+        at: DateTime<Utc>,
+    },
+    /// Follows the result of a revisit: whether the page changed and when to come back.
+    Revisited { url: Url, changed: bool, interval_secs: u64, at: DateTime<Utc> },
+    /// The full state of a URL. Every other outcome is folded into the URL's
+    /// previous state and written as one of these, so a URL's latest line says
+    /// all there is to know about it. Logs written before that was the case
+    /// still contain the other kinds and are folded on startup.
+    State { url: Url, state: UrlState },
+}
+
+impl Record {
+    fn enqueued(entry: &FrontierEntry) -> Self {
+        Record::Enqueued { url: entry.url.clone(), lastmod: entry.lastmod, priority: entry.priority, depth: entry.depth }
+    }
+
+    fn url(&self) -> &Url {
+        match self {
+            Record::Enqueued { url, .. }
+            | Record::Fetched { url, .. }
+            | Record::NotModified { url, .. }
+            | Record::Failed { url, .. }
+            | Record::Skipped { url, .. }
+            | Record::Revisited { url, .. }
+            | Record::State { url, .. } => url,
+        }
+    }
+}
+
+/// What a previous run left behind.
+pub struct Resumed {
+    /// URLs that were queued but never finished, in the order they were queued.
+    pub pending: Vec<FrontierEntry>,
+    /// Every URL the crawl has ever queued.
+    pub seen: Box<dyn SeenSet>,
+    /// Host -> distinct URLs the crawl has queued there.
+    pub pages: HashMap<String, usize>,
+}
+
+/// Crawl state kept in an append-only JSON-lines log, so a crawl can be killed
+/// and resumed. Records are flushed as they are written; each startup replays
+/// the log and rewrites it compacted to one record per URL.
+///
+/// Per-URL state stays on disk: memory holds only the offset of each URL's
+/// latest `State` line, which is read back when the state is needed.
+pub struct CrawlStore {
+    log: Mutex<Log>,
+}
+
+struct Log {
+    writer: fs::File,
+    reader: io::BufReader<fs::File>,
+    /// Length of the log, where the next line will start.
+    len: u64,
+    /// URL -> offset of its latest `State` line.
+    index: HashMap<UrlKey, u64>,
+}
+
+impl CrawlStore {
+    /// Replays the log at `path`, remembering every URL it has queued in `seen`.
+    pub fn open(path: &Path, mut seen: Box<dyn SeenSet>) -> Result<(Self, Resumed)> {
+        let replayed = replay(path, seen.as_mut()).with_context(|| format!("cannot read crawl state {}", path.display()))?;
+        let index = compact(path, &replayed)
+            .with_context(|| format!("cannot compact crawl state {}", path.display()))?;
+
+        let writer = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let len = writer.metadata()?.len();
+        let reader = io::BufReader::new(fs::File::open(path)?);
+        let store = Self { log: Mutex::new(Log { writer, reader, len, index }) };
+        Ok((store, Resumed { pending: replayed.pending, seen, pages: replayed.pages }))
+    }
+
+    /// The latest known state of `url`, if it was ever crawled.
+    pub fn state(&self, url: &Url) -> Option<UrlState> {
+        self.lock().read(&url_key(url)).filter(|(u, _)| u == url).map(|(_, state)| state)
+    }
+
+    /// The URL with `key` and its latest state, if it was ever crawled.
+    pub fn lookup(&self, key: &UrlKey) -> Option<(Url, UrlState)> {
+        self.lock().read(key)
+    }
+
+    /// Calls `f` with every crawled URL and its state, reading the log in order.
+    pub fn for_each(&self, mut f: impl FnMut(&Url, &UrlState)) {
+        let mut log = self.lock();
+        let mut offsets: Vec<u64> = log.index.values().copied().collect();
+        offsets.sort_unstable();
+        for offset in offsets {
+            if let Some((url, state)) = log.read_at(offset) {
+                f(&url, &state);
+            }
+        }
+    }
+
+    pub fn enqueued(&self, entry: &FrontierEntry) {
+        self.append(Record::enqueued(entry));
+    }
+
+    /// Records a fetched page. `canonical` is the other URL it names as
+    /// canonical, if any, after normalization.
+    pub fn fetched(&self, url: &Url, depth: u32, page: &CrawledPage, duplicate_of: Option<Url>, canonical: Option<Url>) {
+        self.append(Record::Fetched {
+            url: url.clone(),
+            http_status: page.status_code,
+            etag: page.etag.clone(),
+            last_modified: page.last_modified.clone(),
+            content_hash: page.content_hash.clone(),
+            fingerprint: page.fingerprint,
+            duplicate_of,
+            canonical,
+            depth,
+            at: Utc::now(),
+        });
+    }
+
+    pub fn not_modified(&self, url: &Url) {
+        self.append(Record::NotModified { url: url.clone(), at: Utc::now() });
+    }
+
+    /// Records a failed fetch: a network error, or an error page with `http_status`.
+    /// `interval` is how long until the page is tried again, if it is revisited.
+    pub fn failed(&self, url: &Url, depth: u32, http_status: Option<u16>, error: String, interval: Option<Duration>) {
+        self.append(Record::Failed {
+            url: url.clone(),
+            error,
+            http_status,
+            interval_secs: interval.map(|i| i.as_secs()),
+            depth,
+            at: Utc::now(),
+        });
+    }
+
+    pub fn skipped(&self, url: &Url, depth: u32, reason: &str) {
+        self.append(Record::Skipped { url: url.clone(), reason: reason.to_string(), depth, at: Utc::now() });
+    }
+
+    pub fn revisited(&self, url: &Url, changed: bool, interval: Duration) {
+        self.append(Record::Revisited { url: url.clone(), changed, interval_secs: interval.as_secs(), at: Utc::now() });
+    }
+
+    /// Folds an outcome into the URL's state and appends the result. A failed
+    /// write is reported but does not stop the crawl; at worst the URL is crawled again.
+    fn append(&self, record: Record) {
+        let mut log = self.lock();
+        let record = match record {
+            Record::Enqueued { .. } | Record::State { .. } => record,
+            outcome => {
+                let url = outcome.url().clone();
+                let previous = log.read(&url_key(&url)).filter(|(u, _)| *u == url).map(|(_, state)| state);
+                match next_state(previous, &outcome) {
+                    Some(state) => Record::State { url, state },
+                    None => return,
+                }
+            }
+        };
+        if let Err(e) = log.write(&record) {
+            eprintln!("Failed to write crawl state: {}", e);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Log> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Log {
+    fn read(&mut self, key: &UrlKey) -> Option<(Url, UrlState)> {
+        let offset = *self.index.get(key)?;
+        self.read_at(offset)
+    }
+
+    fn read_at(&mut self, offset: u64) -> Option<(Url, UrlState)> {
+        match read_state(&mut self.reader, offset) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("Failed to read crawl state: {}", e);
+                None
+            }
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record).context("cannot encode crawl record")?;
+        line.push(b'\n');
+        if let Err(e) = self.writer.write_all(&line).and_then(|_| self.writer.flush()) {
+            // a partial line may have been written; later lines start after it
+            self.len = self.writer.metadata().map(|m| m.len()).unwrap_or(self.len);
+            return Err(e.into());
+        }
+        if let Record::State { url, .. } = record {
+            self.index.insert(url_key(url), self.len);
+        }
+        self.len += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Reads the `State` line at `offset`.
+fn read_state(reader: &mut io::BufReader<fs::File>, offset: u64) -> Result<Option<(Url, UrlState)>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    match serde_json::from_str(&line)? {
+        Record::State { url, state } => Ok(Some((url, state))),
+        _ => Ok(None),
+    }
+}
+
+/// The state of a URL after `record`, given its state before. `None` if the
+/// record says nothing about the URL's outcome, or revises a URL never crawled.
+fn next_state(previous: Option<UrlState>, record: &Record) -> Option<UrlState> {
+    match record {
+        Record::Enqueued { .. } => previous,
+        Record::State { state, .. } => Some(state.clone()),
+        Record::Revisited { changed, interval_secs, at, .. } => {
+            let mut state = previous?;
+            let history = &mut state.history;
+            history.revisits += 1;
+            if *changed {
+                history.changes += 1;
+                history.last_changed = Some(*at);
+            }
+            history.interval_secs = Some(*interval_secs);
+            Some(state)
+        }
+        Record::Fetched { http_status, etag, last_modified, content_hash, fingerprint, duplicate_of, canonical, depth, at, .. } => Some(UrlState {
+            status: CrawlStatus::Fetched,
+            http_status: Some(*http_status),
+            etag: etag.clone(),
+            last_modified: last_modified.clone(),
+            content_hash: Some(content_hash.clone()),
+            fingerprint: *fingerprint,
+            duplicate_of: duplicate_of.clone(),
+            canonical: canonical.clone(),
+            updated: *at,
+            history: previous.map(|s| s.history).unwrap_or_default(),
+            depth: *depth,
+        }),
+        // these keep the validators, hash and history of the last successful fetch
+        Record::NotModified { at, .. } => {
+            let depth = previous.as_ref().map_or(0, |s| s.depth);
+            Some(outcome(previous, CrawlStatus::NotModified, depth, *at))
+        }
+        Record::Failed { http_status, interval_secs, depth, at, .. } => {
+            let mut state = outcome(previous, CrawlStatus::Failed, *depth, *at);
+            state.http_status = http_status.or(state.http_status);
+            // a failure backs the interval off but is not a revisit
+            if interval_secs.is_some() {
+                state.history.interval_secs = *interval_secs;
+            }
+            Some(state)
+        }
+        Record::Skipped { depth, at, .. } => Some(outcome(previous, CrawlStatus::Skipped, *depth, *at)),
+    }
+}
+
+fn outcome(previous: Option<UrlState>, status: CrawlStatus, depth: u32, at: DateTime<Utc>) -> UrlState {
+    match previous {
+        Some(previous) => UrlState { status, updated: at, depth, ..previous },
+        None => UrlState {
+            status,
+            http_status: None,
+            etag: None,
+            last_modified: None,
+            content_hash: None,
+            fingerprint: None,
+            duplicate_of: None,
+            canonical: None,
+            updated: at,
+            history: ChangeHistory::default(),
+            depth,
+        },
+    }
+}
+
+/// The log as replayed, before compaction.
+struct Replayed {
+    /// URL -> offset of its latest `State` line, for URLs whose latest line is one.
+    index: HashMap<UrlKey, u64>,
+    /// URLs whose latest lines are older kinds of record, folded into a state.
+    folded: HashMap<UrlKey, (Url, UrlState)>,
+    pending: Vec<FrontierEntry>,
+    pages: HashMap<String, usize>,
+}
+
+/// Reads the log, adding every URL it mentions to `seen`. A trailing line
+/// without a newline is the remains of an interrupted write and is ignored;
+/// any other malformed line is an error.
+fn replay(path: &Path, seen: &mut dyn SeenSet) -> Result<Replayed> {
+    let mut replayed = Replayed { index: HashMap::new(), folded: HashMap::new(), pending: Vec::new(), pages: HashMap::new() };
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(replayed),
+        Err(e) => return Err(e.into()),
+    };
+    // states of older records are folded onto the line a URL was last written at
+    let mut states = io::BufReader::new(fs::File::open(path)?);
+    // URL -> queued entry, for URLs that have not finished yet
+    let mut pending: HashMap<UrlKey, (usize, FrontierEntry)> = HashMap::new();
+
+    let mut reader = io::BufReader::new(file);
+    let mut line = String::new();
+    let mut line_no = 0;
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        line_no += 1;
+        if !line.ends_with('\n') {
+            eprintln!("Ignoring incomplete record at end of {}", path.display());
+            break;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .with_context(|| format!("line {} is corrupt", line_no))?;
+        let url = record.url();
+        let key = url_key(url);
+        if seen.insert(url) {
+            *replayed.pages.entry(frontier::host_key(url)).or_default() += 1;
+        }
+        match &record {
+            Record::Enqueued { url, lastmod, priority, depth } => {
+                let entry = FrontierEntry { url: url.clone(), lastmod: *lastmod, priority: *priority, depth: *depth };
+                pending.insert(key, (line_no, entry));
+            }
+            Record::State { .. } => {
+                replayed.index.insert(key, offset);
+                replayed.folded.remove(&key);
+                pending.remove(&key);
+            }
+            _ => {
+                let previous = match replayed.folded.remove(&key) {
+                    Some((_, state)) => Some(state),
+                    None => match replayed.index.get(&key) {
+                        Some(&at) => read_state(&mut states, at)?.map(|(_, state)| state),
+                        None => None,
+                    },
+                };
+                if let Some(state) = next_state(previous, &record) {
+                    replayed.folded.insert(key, (url.clone(), state));
+                }
+                pending.remove(&key);
+            }
+        }
+        offset += read as u64;
+    }
+
+    let mut pending: Vec<(usize, FrontierEntry)> = pending.into_values().collect();
+    pending.sort_by_key(|(line_no, _)| *line_no);
+    replayed.pending = pending.into_iter().map(|(_, entry)| entry).collect();
+    Ok(replayed)
+}
+
+/// Rewrites the log as one `State` record per crawled URL followed by the
+/// pending queue, via a temp file and a rename so a crash leaves either log
+/// intact. Returns where each URL's state now starts.
+fn compact(path: &Path, replayed: &Replayed) -> Result<HashMap<UrlKey, u64>> {
+    let tmp_path = tmp_path_for(path);
+    let mut index = HashMap::with_capacity(replayed.index.len() + replayed.folded.len());
+    {
+        let mut writer = io::BufWriter::new(fs::File::create(&tmp_path)?);
+        let mut len = 0;
+        // copy the latest state lines as they are, in log order so the old log is read front to back
+        let mut lines: Vec<(u64, UrlKey)> = replayed.index.iter()
+            .filter(|(key, _)| !replayed.folded.contains_key(*key))
+            .map(|(key, offset)| (*offset, *key))
+            .collect();
+        lines.sort_unstable();
+        if !lines.is_empty() {
+            let mut reader = io::BufReader::new(fs::File::open(path)?);
+            let mut line = Vec::new();
+            for (offset, key) in lines {
+                reader.seek(SeekFrom::Start(offset))?;
+                line.clear();
+                reader.read_until(b'\n', &mut line)?;
+                writer.write_all(&line)?;
+                index.insert(key, len);
+                len += line.len() as u64;
+            }
+        }
+        for (key, (url, state)) in &replayed.folded {
+            let mut line = serde_json::to_vec(&Record::State { url: url.clone(), state: state.clone() })?;
+            line.push(b'\n');
+            writer.write_all(&line)?;
+            index.insert(*key, len);
+            len += line.len() as u64;
+        }
+        for entry in &replayed.pending {
+            serde_json::to_writer(&mut writer, &Record::enqueued(entry))?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(index)
+}
+
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seen::ExactSeenSet;
+
+    /// A crawl log in the temp directory, removed when dropped.
+    struct TempLog(PathBuf);
+
+    impl TempLog {
+        fn new(name: &str, lines: &[&str]) -> Self {
+            let path = std::env::temp_dir().join(format!("crawl-store-{}-{}.jsonl", std::process::id(), name));
+            fs::write(&path, lines.concat()).unwrap();
+            Self(path)
+        }
+
+        fn open(&self) -> (CrawlStore, Resumed) {
+            CrawlStore::open(&self.0, Box::new(ExactSeenSet::default())).unwrap()
+        }
+
+        fn lines(&self) -> Vec<String> {
+            fs::read_to_string(&self.0).unwrap().lines().map(str::to_string).collect()
+        }
+    }
+
+    impl Drop for TempLog {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(tmp_path_for(&self.0));
+        }
+    }
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    const A: &str = "https://example.com/a";
+    const B: &str = "https://example.com/b";
+    const C: &str = "https://example.com/c";
+
+    #[test]
+    fn replay_folds_records_into_state() {
+        let log = TempLog::new("replay", &[
+            "{\"op\":\"enqueued\",\"url\":\"https://example.com/a\",\"priority\":0.5,\"depth\":1}\n",
+            "{\"op\":\"enqueued\",\"url\":\"https://example.com/b\",\"priority\":0.8,\"depth\":2}\n",
+            "{\"op\":\"enqueued\",\"url\":\"https://example.com/c\",\"priority\":0.5}\n",
+            "{\"op\":\"fetched\",\"url\":\"https://example.com/a\",\"http_status\":200,\"etag\":\"\\\"v1\\\"\",\"last_modified\":null,\"content_hash\":\"h1\",\"depth\":1,\"at\":\"2026-01-01T00:00:00Z\"}\n",
+            "{\"op\":\"revisited\",\"url\":\"https://example.com/a\",\"changed\":true,\"interval_secs\":600,\"at\":\"2026-01-01T00:00:00Z\"}\n",
+            "{\"op\":\"state\",\"url\":\"https://example.com/c\",\"state\":{\"status\":\"fetched\",\"content_hash\":\"h3\",\"updated\":\"2026-01-01T00:00:00Z\",\"depth\":3}}\n",
+            "{\"op\":\"not_modified\",\"url\":\"https://example.com/c\",\"at\":\"2026-01-02T00:00:00Z\"}\n",
+        ]);
+        let (store, resumed) = log.open();
+
+        let a = store.state(&url(A)).unwrap();
+        assert_eq!(a.status, CrawlStatus::Fetched);
+        assert_eq!((a.etag.as_deref(), a.content_hash.as_deref(), a.depth), (Some("\"v1\""), Some("h1"), 1));
+        assert_eq!((a.history.revisits, a.history.changes, a.history.interval_secs), (1, 1, Some(600)));
+
+        // a delta on top of a full state keeps what the state said
+        let c = store.state(&url(C)).unwrap();
+        assert_eq!(c.status, CrawlStatus::NotModified);
+        assert_eq!((c.content_hash.as_deref(), c.depth), (Some("h3"), 3));
+
+        assert!(store.state(&url(B)).is_none());
+        let pending: Vec<(String, u32)> = resumed.pending.iter().map(|e| (e.url.to_string(), e.depth)).collect();
+        assert_eq!(pending, [(B.to_string(), 2)]);
+        assert!(resumed.seen.contains(&url(A)) && resumed.seen.contains(&url(B)) && resumed.seen.contains(&url(C)));
+        assert_eq!(resumed.pages.get("example.com"), Some(&3));
+    }
+
+    #[test]
+    fn replay_ignores_a_torn_last_line() {
+        let log = TempLog::new("torn", &[
+            "{\"op\":\"enqueued\",\"url\":\"https://example.com/a\",\"priority\":0.5}\n",
+            "{\"op\":\"state\",\"url\":\"https://example.com/b\",\"state\":{\"status\":\"fetched\",\"updated\":\"2026-01-01T00:00:00Z\"}}\n",
+            "{\"op\":\"fetched\",\"url\":\"https://example.com/a\",\"http_st",
+        ]);
+        let (store, resumed) = log.open();
+
+        // the fetch of a never finished, so a is still queued
+        assert!(store.state(&url(A)).is_none());
+        assert_eq!(resumed.pending.len(), 1);
+        assert_eq!(resumed.pending[0].url, url(A));
+        assert_eq!(store.state(&url(B)).unwrap().status, CrawlStatus::Fetched);
+        assert!(log.lines().iter().all(|line| serde_json::from_str::<Record>(line).is_ok()));
+    }
+
+    #[test]
+    fn replay_rejects_a_corrupt_line_in_the_middle() {
+        let log = TempLog::new("corrupt", &[
+            "{\"op\":\"enqueued\",\"url\":\"https://example.com/a\"\n",
+            "{\"op\":\"enqueued\",\"url\":\"https://example.com/b\",\"priority\":0.5}\n",
+        ]);
+        assert!(CrawlStore::open(&log.0, Box::new(ExactSeenSet::default())).is_err());
+    }
+
+    #[test]
+    fn compaction_keeps_the_latest_state_per_url() {
+        let log = TempLog::new("compact", &[]);
+        {
+            let (store, _) = log.open();
+            store.enqueued(&FrontierEntry::new(url(A)));
+            store.enqueued(&FrontierEntry::new(url(B)));
+            store.enqueued(&FrontierEntry::new(url(C)));
+            store.failed(&url(A), 0, Some(503), "HTTP status 503".to_string(), None);
+            store.skipped(&url(A), 4, "robots.txt");
+            store.failed(&url(B), 1, None, "timed out".to_string(), Some(Duration::from_secs(90)));
+            store.revisited(&url(B), false, Duration::from_secs(120));
+            assert_eq!(store.state(&url(A)).unwrap().status, CrawlStatus::Skipped);
+        }
+
+        let (store, resumed) = log.open();
+        let lines = log.lines();
+        // one state per finished URL, then the URL still queued
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].contains("\"op\":\"enqueued\"") && lines[2].contains(C));
+        assert_eq!(resumed.pending.len(), 1);
+
+        let (a, state) = store.lookup(&url_key(&url(A))).unwrap();
+        assert_eq!((a, state.status, state.http_status, state.depth), (url(A), CrawlStatus::Skipped, Some(503), 4));
+        let (b, state) = store.lookup(&url_key(&url(B))).unwrap();
+        assert_eq!((b, state.status, state.history.revisits, state.history.interval_secs), (url(B), CrawlStatus::Failed, 1, Some(120)));
+        assert!(store.lookup(&url_key(&url(C))).is_none());
+
+        // states appended after compaction are found too
+        store.not_modified(&url(B));
+        assert_eq!(store.lookup(&url_key(&url(B))).unwrap().1.status, CrawlStatus::NotModified);
+        let mut visited = Vec::new();
+        store.for_each(|url, state| visited.push((url.to_string(), state.status)));
+        visited.sort_by(|x, y| x.0.cmp(&y.0));
+        assert_eq!(visited, [(A.to_string(), CrawlStatus::Skipped), (B.to_string(), CrawlStatus::NotModified)]);
+    }
+}
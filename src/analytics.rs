@@ -0,0 +1,111 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::warn;
+use crate::config::QueryLogConfig;
+
+/// A structured analytics event, written as one JSON line.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event<'a> {
+    Query {
+        timestamp: DateTime<Utc>,
+        query: &'a str,
+        terms: &'a [String],
+        /// Documents matched, not just those on the returned page.
+        hits: usize,
+        latency_ms: f64,
+        results: Vec<&'a str>,
+    },
+    Click {
+        timestamp: DateTime<Utc>,
+        query: &'a str,
+        doc: &'a str,
+        rank: Option<usize>,
+    },
+}
+
+/// Append-only JSONL event log that rotates by size: `queries.jsonl` is renamed
+/// to `queries.jsonl.1`, `.1` to `.2` and so on, dropping the oldest.
+pub struct EventLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Mutex<Option<(fs::File, u64)>>,
+}
+
+impl EventLog {
+    pub fn open(config: &QueryLogConfig) -> io::Result<Self> {
+        let file = if config.enabled {
+            if let Some(dir) = config.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            Some(open_append(&config.path)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            path: config.path.clone(),
+            max_bytes: config.max_bytes,
+            max_files: config.max_files,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records an event. Logging failures are reported but never fail the request.
+    pub fn record(&self, event: &Event) {
+        let mut guard = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if guard.is_none() {
+            return;
+        }
+        if let Err(e) = self.write_event(&mut guard, event) {
+            warn!(path = %self.path.display(), error = %e, "Failed to write event");
+        }
+    }
+
+    fn write_event(&self, slot: &mut Option<(fs::File, u64)>, event: &Event) -> io::Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        let size = slot.as_ref().map_or(0, |(_, size)| *size);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            *slot = None;
+            self.rotate()?;
+            *slot = Some(open_append(&self.path)?);
+        }
+        if let Some((file, size)) = slot {
+            file.write_all(&line)?;
+            *size += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_file(rotated_path(&self.path, self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<(fs::File, u64)> {
+    let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use crate::config::{AuthConfig, CorsConfig, RateLimitConfig};
+
+/// What an API key is allowed to do. `Admin` implies `Read`.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    #[default]
+    Read,
+    Admin,
+}
+
+/// The caller a request is attributed to for rate limiting.
+pub struct Client {
+    pub id: String,
+    pub requests_per_second: Option<f64>,
+}
+
+pub enum AuthError {
+    /// No key, or a key that is not configured.
+    Unauthorized,
+    /// A valid key without the scope the route needs.
+    Forbidden,
+}
+
+pub struct Authenticator {
+    config: AuthConfig,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Self {
+        Self { config }
+    }
+
+    /// Checks `presented_key` against the configured keys. Authentication is off
+    /// when no keys are configured; `remote` identifies anonymous callers.
+    pub fn authorize(&self, presented_key: Option<&str>, required: Scope, remote: &str) -> Result<Client, AuthError> {
+        let anonymous = Client { id: format!("ip:{remote}"), requests_per_second: None };
+        if self.config.keys.is_empty() {
+            return Ok(anonymous);
+        }
+
+        let Some(presented) = presented_key else {
+            return if self.config.anonymous_read && required == Scope::Read {
+                Ok(anonymous)
+            } else {
+                Err(AuthError::Unauthorized)
+            };
+        };
+
+        // compare against every key so timing does not reveal which prefix matched
+        let mut matched = None;
+        for key in &self.config.keys {
+            if constant_time_eq(key.key.as_bytes(), presented.as_bytes()) {
+                matched = Some(key);
+            }
+        }
+        let key = matched.ok_or(AuthError::Unauthorized)?;
+        if key.scope < required {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(Client { id: format!("key:{}", key.name), requests_per_second: key.requests_per_second })
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-client token buckets: each client may burst up to `burst` requests and
+/// then sustain `requests_per_second`.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// Buckets are pruned once this many clients are tracked.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self { config, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// Takes a token for `client`, or returns how long until one is available.
+    pub fn check(&self, client: &Client) -> Result<(), Duration> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let rate = client.requests_per_second.unwrap_or(self.config.requests_per_second);
+        let burst = self.config.burst.max(1.0);
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // a bucket that has refilled completely carries no state worth keeping
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst);
+        }
+
+        let bucket = buckets.entry(client.id.clone()).or_insert(Bucket { tokens: burst, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        } else {
+            Err(Duration::from_secs(60))
+        }
+    }
+}
+
+/// Returns the `Access-Control-Allow-Origin` value for a request's `Origin`, if it is allowed.
+pub fn allowed_origin<'a>(config: &CorsConfig, origin: Option<&'a str>) -> Option<&'a str> {
+    if config.allowed_origins.iter().any(|o| o == "*") {
+        return Some("*");
+    }
+    let origin = origin?;
+    config.allowed_origins.iter().any(|o| o == origin).then_some(origin)
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::CacheConfig;
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub invalidations: u64,
+    pub entries: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    tick: u64,
+}
+
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    // recency order: the smallest tick is the least recently used key
+    order: BTreeMap<u64, String>,
+    next_tick: u64,
+    generation: u64,
+    stats: CacheStats,
+}
+
+/// A thread-safe LRU cache with a per-entry TTL.
+///
+/// Every lookup carries the generation of the index it is answered from; when
+/// the generation changes the whole cache is dropped, so results computed
+/// against a swapped or updated index are never served.
+pub struct QueryCache<V> {
+    inner: Mutex<Inner<V>>,
+    max_entries: usize,
+    ttl: Duration,
+}
+
+impl<V: Clone> QueryCache<V> {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                next_tick: 0,
+                generation: 0,
+                stats: CacheStats::default(),
+            }),
+            max_entries: if config.enabled { config.max_entries } else { 0 },
+            ttl: Duration::from_secs(config.ttl_secs),
+        }
+    }
+
+    pub fn get(&self, key: &str, generation: u64) -> Option<V> {
+        if self.max_entries == 0 {
+            return None;
+        }
+        let mut inner = self.lock();
+        inner.sync_generation(generation);
+
+        let tick = inner.next_tick;
+        let Some(entry) = inner.entries.get_mut(key) else {
+            inner.stats.misses += 1;
+            return None;
+        };
+        if entry.inserted.elapsed() > self.ttl {
+            let old_tick = entry.tick;
+            inner.entries.remove(key);
+            inner.order.remove(&old_tick);
+            inner.stats.expirations += 1;
+            inner.stats.misses += 1;
+            return None;
+        }
+
+        let old_tick = std::mem::replace(&mut entry.tick, tick);
+        let value = entry.value.clone();
+        inner.order.remove(&old_tick);
+        inner.order.insert(tick, key.to_string());
+        inner.next_tick += 1;
+        inner.stats.hits += 1;
+        Some(value)
+    }
+
+    pub fn insert(&self, key: String, value: V, generation: u64) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut inner = self.lock();
+        inner.sync_generation(generation);
+
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        if let Some(old) = inner.entries.insert(key.clone(), Entry { value, inserted: Instant::now(), tick }) {
+            inner.order.remove(&old.tick);
+        }
+        inner.order.insert(tick, key);
+
+        while inner.entries.len() > self.max_entries {
+            let Some((_, oldest)) = inner.order.pop_first() else { break };
+            inner.entries.remove(&oldest);
+            inner.stats.evictions += 1;
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats { entries: inner.entries.len(), ..inner.stats }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner<V>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<V> Inner<V> {
+    fn sync_generation(&mut self, generation: u64) {
+        if generation != self.generation {
+            self.entries.clear();
+            self.order.clear();
+            self.generation = generation;
+            self.stats.invalidations += 1;
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::auth::Scope;
+
+/// Settings for `serve`, read from a JSON file passed with `--config`.
+/// Every field is optional and falls back to its default.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub addr: String,
+    /// Threads handling requests concurrently.
+    pub workers: usize,
+    pub query_log: QueryLogConfig,
+    pub cache: CacheConfig,
+    pub auth: AuthConfig,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    /// Serves HTTPS instead of HTTP when set.
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: "0.0.0.0:6969".to_string(),
+            workers: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            query_log: QueryLogConfig::default(),
+            cache: CacheConfig::default(),
+            auth: AuthConfig::default(),
+            cors: CorsConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            tls: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryLogConfig {
+    pub enabled: bool,
+    pub path: PathBuf,
+    /// The active file is rotated once it would grow past this size.
+    pub max_bytes: u64,
+    /// Number of rotated files kept next to the active one.
+    pub max_files: usize,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("logs/queries.jsonl"),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub max_entries: usize,
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: 1000,
+            ttl_secs: 300,
+        }
+    }
+}
+
+/// API keys are sent as `X-API-Key: <key>` or `Authorization: Bearer <key>`.
+/// With no keys configured, authentication is disabled.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub keys: Vec<ApiKeyConfig>,
+    /// Lets callers without a key use read-only routes, e.g. the bundled UI.
+    pub anonymous_read: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub scope: Scope,
+    /// Overrides the default rate limit for this key.
+    #[serde(default)]
+    pub requests_per_second: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API from a browser; `"*"` allows any.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self { allowed_origins: vec!["*".to_string()] }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Sustained requests per second per API key, or per IP for anonymous callers.
+    pub requests_per_second: f64,
+    /// Requests a client may make in a burst before being limited.
+    pub burst: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            requests_per_second: 10.0,
+            burst: 20.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain.
+    pub cert_path: PathBuf,
+    /// PEM private key, PKCS#8 or RSA.
+    pub key_path: PathBuf,
+    /// How often the files are checked for changes; the listener is rebound with
+    /// the new certificate when they change.
+    #[serde(default = "default_reload_check_secs")]
+    pub reload_check_secs: u64,
+}
+
+fn default_reload_check_secs() -> u64 {
+    10
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot read {}: {e}", path.display()))
+        })?;
+        serde_json::from_str(&content).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} is invalid: {e}", path.display()))
+        })
+    }
+}
@@ -0,0 +1,144 @@
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// Bodies smaller than this are sent uncompressed; the framing overhead outweighs the gain.
+const MIN_COMPRESS_BYTES: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    pub fn header_value(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Brotli => Some("br"),
+        }
+    }
+
+    /// The ETag of this encoding of a body whose uncompressed ETag is `etag`.
+    /// Each content-coding is a different representation and needs its own
+    /// strong validator (RFC 9110, section 8.8.3).
+    pub fn etag(self, etag: &str) -> String {
+        let suffix = match self {
+            Encoding::Identity => return etag.to_string(),
+            Encoding::Gzip => "-gz",
+            Encoding::Brotli => "-br",
+        };
+        match etag.strip_suffix('"') {
+            Some(opaque) => format!("{opaque}{suffix}\""),
+            None => format!("{etag}{suffix}"),
+        }
+    }
+}
+
+/// Picks the best encoding the client accepts from an `Accept-Encoding` header,
+/// preferring brotli over gzip and honoring `q=0` exclusions.
+pub fn negotiate(accept_encoding: Option<&str>) -> Encoding {
+    let Some(header) = accept_encoding else {
+        return Encoding::Identity;
+    };
+    let mut best = (Encoding::Identity, 0.0);
+    for item in header.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let encoding = match name.as_str() {
+            "br" => Encoding::Brotli,
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            _ => continue,
+        };
+        let better = q > best.1 || (q == best.1 && encoding == Encoding::Brotli);
+        if q > 0.0 && better {
+            best = (encoding, q);
+        }
+    }
+    best.0
+}
+
+/// The encoding a `len`-byte body is sent with when the client prefers `encoding`.
+pub fn applied(encoding: Encoding, len: usize) -> Encoding {
+    if len < MIN_COMPRESS_BYTES { Encoding::Identity } else { encoding }
+}
+
+/// Compresses `body`, or returns `None` when it is not worth it.
+/// `max_effort` is for content compressed once and served many times.
+pub fn compress(body: &[u8], encoding: Encoding, max_effort: bool) -> io::Result<Option<Vec<u8>>> {
+    let compressed = match applied(encoding, body.len()) {
+        Encoding::Identity => return Ok(None),
+        Encoding::Gzip => {
+            let level = if max_effort { Compression::best() } else { Compression::default() };
+            let mut encoder = GzEncoder::new(Vec::new(), level);
+            encoder.write_all(body)?;
+            encoder.finish()?
+        }
+        Encoding::Brotli => {
+            let quality = if max_effort { 11 } else { 5 };
+            let mut out = Vec::new();
+            {
+                let mut writer = brotli::CompressorWriter::new(&mut out, 4096, quality, 22);
+                writer.write_all(body)?;
+            }
+            out
+        }
+    };
+    Ok(Some(compressed))
+}
+
+/// A strong ETag derived from the body bytes, for the uncompressed body.
+pub fn etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(body);
+    format!("\"{:016x}-{:x}\"", hasher.finish(), body.len())
+}
+
+/// Whether an `If-None-Match` header value matches `etag`.
+pub fn matches_if_none_match(header: Option<&str>, etag: &str) -> bool {
+    header.is_some_and(|value| {
+        value.split(',').map(str::trim).any(|candidate| {
+            candidate == "*" || candidate.trim_start_matches("W/") == etag
+        })
+    })
+}
+
+/// The search UI, compiled into the binary with its compressed variants prepared once.
+pub struct EmbeddedAsset {
+    pub body: &'static [u8],
+    pub etag: String,
+    gzip: Option<Vec<u8>>,
+    brotli: Option<Vec<u8>>,
+}
+
+impl EmbeddedAsset {
+    fn new(body: &'static [u8]) -> Self {
+        Self {
+            body,
+            etag: etag(body),
+            gzip: compress(body, Encoding::Gzip, true).ok().flatten(),
+            brotli: compress(body, Encoding::Brotli, true).ok().flatten(),
+        }
+    }
+
+    pub fn encoded(&self, encoding: Encoding) -> Option<&[u8]> {
+        match encoding {
+            Encoding::Identity => None,
+            Encoding::Gzip => self.gzip.as_deref(),
+            Encoding::Brotli => self.brotli.as_deref(),
+        }
+    }
+}
+
+pub fn index_html() -> &'static EmbeddedAsset {
+    static INDEX_HTML: OnceLock<EmbeddedAsset> = OnceLock::new();
+    INDEX_HTML.get_or_init(|| EmbeddedAsset::new(include_bytes!("../index.html")))
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::lexer::tokenize_query;
+use crate::model::InvertedIndex;
+use crate::ranking::{Ranking, TermStats, rank};
+
+/// Graded judgments: query id -> document id -> relevance grade.
+pub type Qrels = HashMap<String, HashMap<String, u32>>;
+
+#[derive(Default, Clone, Copy)]
+pub struct Metrics {
+    pub ndcg: f64,
+    pub ap: f64,
+    pub rr: f64,
+    pub recall: f64,
+}
+
+/// Reads a query set with one `<qid> <query text>` per line. Blank lines and
+/// lines starting with `#` are skipped.
+pub fn load_queries(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)?;
+    let mut queries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (qid, text) = line.split_once(char::is_whitespace).ok_or_else(|| {
+            invalid_data(format!("{} line {}: expected <qid> <query>", path.display(), i + 1))
+        })?;
+        queries.push((qid.to_string(), text.trim().to_string()));
+    }
+    Ok(queries)
+}
+
+/// Reads TREC qrels: `<qid> <iteration> <docid> <relevance>` per line.
+pub fn load_qrels(path: &Path) -> io::Result<Qrels> {
+    let content = fs::read_to_string(path)?;
+    let mut qrels = Qrels::new();
+    for (i, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let [qid, _iteration, docid, rel] = fields[..] else {
+            return Err(invalid_data(format!("{} line {}: expected 4 fields", path.display(), i + 1)));
+        };
+        // negative grades mark judged non-relevant documents
+        let rel: i64 = rel.parse().map_err(|_| {
+            invalid_data(format!("{} line {}: bad relevance {rel:?}", path.display(), i + 1))
+        })?;
+        qrels.entry(qid.to_string()).or_default()
+            .insert(normalize_docid(docid), rel.max(0) as u32);
+    }
+    Ok(qrels)
+}
+
+/// Runs every judged query against the index and returns per-query metrics in query order.
+pub fn evaluate(
+    index: &InvertedIndex,
+    queries: &[(String, String)],
+    qrels: &Qrels,
+    ranking: Ranking,
+    k: usize,
+) -> Vec<(String, Metrics)> {
+    queries.iter()
+        .filter_map(|(qid, text)| {
+            let judgments = qrels.get(qid)?;
+            let terms = tokenize_query(text);
+            let stats = TermStats::new(&terms, index);
+            let run: Vec<String> = rank(index, &terms, ranking, &stats, k)
+                .into_iter()
+                .map(|(path, _, _)| normalize_docid(&path.display().to_string()))
+                .collect();
+            Some((qid.clone(), metrics(&run, judgments, k)))
+        })
+        .collect()
+}
+
+/// Scores the top `k` documents of `run`; every metric uses the same cutoff.
+pub fn metrics(run: &[String], judgments: &HashMap<String, u32>, k: usize) -> Metrics {
+    let grade = |doc: &String| judgments.get(doc).copied().unwrap_or(0);
+    let relevant = judgments.values().filter(|&&g| g > 0).count();
+    if relevant == 0 || k == 0 {
+        return Metrics::default();
+    }
+
+    let dcg = |grades: &mut dyn Iterator<Item = u32>| -> f64 {
+        grades.take(k).enumerate()
+            .map(|(i, g)| (2f64.powi(g as i32) - 1.0) / (i as f64 + 2.0).log2())
+            .sum()
+    };
+    let mut ideal: Vec<u32> = judgments.values().copied().collect();
+    ideal.sort_unstable_by(|a, b| b.cmp(a));
+    let ndcg = dcg(&mut run.iter().map(grade)) / dcg(&mut ideal.into_iter());
+
+    let mut hits = 0;
+    let mut precision_sum = 0.0;
+    let mut rr = 0.0;
+    for (i, doc) in run.iter().take(k).enumerate() {
+        if grade(doc) > 0 {
+            hits += 1;
+            precision_sum += hits as f64 / (i + 1) as f64;
+            if rr == 0.0 {
+                rr = 1.0 / (i + 1) as f64;
+            }
+        }
+    }
+    let recall = hits as f64 / relevant as f64;
+
+    Metrics { ndcg, ap: precision_sum / relevant as f64, rr, recall }
+}
+
+pub fn mean(results: &[(String, Metrics)]) -> Metrics {
+    let n = results.len().max(1) as f64;
+    results.iter().fold(Metrics::default(), |acc, (_, m)| Metrics {
+        ndcg: acc.ndcg + m.ndcg / n,
+        ap: acc.ap + m.ap / n,
+        rr: acc.rr + m.rr / n,
+        recall: acc.recall + m.recall / n,
+    })
+}
+
+pub fn print_report(results: &[(String, Metrics)], ranking: Ranking, k: usize) {
+    let m = mean(results);
+    println!("Ranking: {ranking}  ({} judged queries)", results.len());
+    println!("{:<12} {:>8}", "METRIC", "VALUE");
+    for (name, value) in metric_rows(&m, k) {
+        println!("{:<12} {:>8.4}", name, value);
+    }
+}
+
+/// Prints both configurations side by side, followed by the queries whose nDCG changed.
+pub fn print_comparison(
+    a: &[(String, Metrics)],
+    b: &[(String, Metrics)],
+    ranking_a: Ranking,
+    ranking_b: Ranking,
+    k: usize,
+) {
+    println!("A: {ranking_a}");
+    println!("B: {ranking_b}");
+    println!("{} judged queries\n", a.len());
+    println!("{:<12} {:>8} {:>8} {:>8}", "METRIC", "A", "B", "B-A");
+    for ((name, va), (_, vb)) in metric_rows(&mean(a), k).into_iter().zip(metric_rows(&mean(b), k)) {
+        println!("{:<12} {:>8.4} {:>8.4} {:>+8.4}", name, va, vb, vb - va);
+    }
+
+    let mut changed: Vec<(&String, f64, f64)> = a.iter().zip(b)
+        .map(|((qid, ma), (_, mb))| (qid, ma.ndcg, mb.ndcg))
+        .filter(|(_, na, nb)| (nb - na).abs() > 1e-9)
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+    changed.sort_by(|x, y| (y.2 - y.1).abs().total_cmp(&(x.2 - x.1).abs()));
+    println!("\n{:<12} {:>8} {:>8} {:>8}", "QUERY", "A", "B", "B-A");
+    for (qid, na, nb) in changed {
+        println!("{:<12} {:>8.4} {:>8.4} {:>+8.4}", qid, na, nb, nb - na);
+    }
+}
+
+fn metric_rows(m: &Metrics, k: usize) -> Vec<(String, f64)> {
+    vec![
+        (format!("nDCG@{k}"), m.ndcg),
+        (format!("MAP@{k}"), m.ap),
+        (format!("MRR@{k}"), m.rr),
+        (format!("recall@{k}"), m.recall),
+    ]
+}
+
+/// Document ids are index paths with forward slashes and no leading `./`.
+fn normalize_docid(docid: &str) -> String {
+    let docid = docid.replace('\\', "/");
+    docid.strip_prefix("./").unwrap_or(&docid).to_string()
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(docs: &[&str]) -> Vec<String> {
+        docs.iter().map(|d| d.to_string()).collect()
+    }
+
+    fn judgments(grades: &[(&str, u32)]) -> HashMap<String, u32> {
+        grades.iter().map(|(d, g)| (d.to_string(), *g)).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn graded_ranking() {
+        // x is relevant but never retrieved, b is judged non-relevant
+        let qrels = judgments(&[("a", 3), ("b", 0), ("c", 1), ("x", 2)]);
+        let m = metrics(&run(&["b", "a", "c", "d"]), &qrels, 3);
+
+        // DCG = 7/log2(3) + 1/2, ideal DCG = 7 + 3/log2(3) + 1/2
+        let dcg = 7.0 / 3f64.log2() + 0.5;
+        let ideal = 7.0 + 3.0 / 3f64.log2() + 0.5;
+        assert_close(m.ndcg, dcg / ideal);
+        assert_close(m.ap, (1.0 / 2.0 + 2.0 / 3.0) / 3.0);
+        assert_close(m.rr, 0.5);
+        assert_close(m.recall, 2.0 / 3.0);
+    }
+
+    #[test]
+    fn cutoff_applies_to_every_metric() {
+        let qrels = judgments(&[("a", 1)]);
+        let m = metrics(&run(&["b", "c", "a"]), &qrels, 2);
+        assert_close(m.ndcg, 0.0);
+        assert_close(m.ap, 0.0);
+        assert_close(m.rr, 0.0);
+        assert_close(m.recall, 0.0);
+    }
+
+    #[test]
+    fn no_relevant_documents() {
+        let qrels = judgments(&[("a", 0), ("b", 0)]);
+        let m = metrics(&run(&["a", "b"]), &qrels, 10);
+        assert_eq!((m.ndcg, m.ap, m.rr, m.recall), (0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn zero_cutoff_is_not_nan() {
+        let qrels = judgments(&[("a", 1)]);
+        let m = metrics(&run(&["a"]), &qrels, 0);
+        assert_eq!((m.ndcg, m.ap, m.rr, m.recall), (0.0, 0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::model::DocStats;
+
+/// Prefix marking a search parameter as a facet filter, as in `f.category=gl4`.
+/// Other parameters, such as cache-busters or tracking tags, are left alone.
+const FILTER_PREFIX: &str = "f.";
+
+/// Value counts per facet field, e.g. `{"category": {"gl4": 12, "es3": 4}}`.
+pub type FacetCounts = BTreeMap<String, BTreeMap<String, usize>>;
+
+/// Restricts results to documents whose facet fields have one of the given
+/// values. Clauses on different fields must all match; `f.category=gl4,es3`
+/// matches either value.
+#[derive(Default)]
+pub struct FacetFilter {
+    clauses: BTreeMap<String, Vec<String>>,
+}
+
+impl FacetFilter {
+    /// Builds a filter from the `f.<field>` query parameters.
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        let clauses = params.iter()
+            .filter_map(|(name, values)| Some((name.strip_prefix(FILTER_PREFIX)?, values)))
+            .map(|(field, values)| {
+                let mut values: Vec<String> = split_list(values);
+                values.sort();
+                (field.to_string(), values)
+            })
+            .filter(|(_, values)| !values.is_empty())
+            .collect();
+        Self { clauses }
+    }
+
+    pub fn matches(&self, doc: &DocStats) -> bool {
+        self.matches_except(doc, None)
+    }
+
+    fn matches_except(&self, doc: &DocStats, skip: Option<&str>) -> bool {
+        self.clauses.iter()
+            .filter(|(field, _)| skip != Some(field.as_str()))
+            .all(|(field, values)| doc.facets.get(field).is_some_and(|v| values.contains(v)))
+    }
+
+    /// A canonical form of the filter, independent of parameter order.
+    pub fn cache_key(&self) -> String {
+        self.clauses.iter()
+            .map(|(field, values)| format!("{field}={}", values.join(",")))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+/// Splits a comma-separated parameter value, dropping empty items.
+pub fn split_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+}
+
+/// Counts the values of each of `fields` among `docs`. A field's own clause is
+/// left out when counting it, so the counts show what selecting another value
+/// of that field would return rather than only the already selected one.
+pub fn count(docs: &[&DocStats], fields: &[String], filter: &FacetFilter) -> FacetCounts {
+    fields.iter().map(|field| {
+        let mut values = BTreeMap::new();
+        for doc in docs.iter().filter(|doc| filter.matches_except(doc, Some(field))) {
+            if let Some(value) = doc.facets.get(field) {
+                *values.entry(value.clone()).or_default() += 1;
+            }
+        }
+        (field.clone(), values)
+    }).collect()
+}
@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use crate::model::DocStats;
+use crate::ranking::sort_by_score;
+
+/// A typed per-document value that can be range-filtered and sorted on.
+/// Dates serialize as RFC 3339 strings, numbers as JSON numbers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Number(f64),
+    Date(DateTime<Utc>),
+}
+
+impl FieldValue {
+    /// Parses a date (`2025-01-01`, `2025-01` or RFC 3339) or a number.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+            return Some(FieldValue::Date(date.with_timezone(&Utc)));
+        }
+        let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d"));
+        if let Ok(date) = date {
+            return Some(FieldValue::Date(date.and_hms_opt(0, 0, 0)?.and_utc()));
+        }
+        s.parse().ok().filter(|n: &f64| n.is_finite()).map(FieldValue::Number)
+    }
+
+    /// Values of different kinds are not comparable.
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (FieldValue::Number(a), FieldValue::Number(b)) => a.partial_cmp(b),
+            (FieldValue::Date(a), FieldValue::Date(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        })
+    }
+}
+
+/// Range clauses such as `modified>2025-01-01` or `size<=4096`, all of which must hold.
+/// A document without the field, or with a value of another kind, does not match.
+#[derive(Default)]
+pub struct RangeFilter {
+    clauses: Vec<(String, Op, FieldValue)>,
+}
+
+impl RangeFilter {
+    /// Removes the range clauses from parsed query parameters and returns them.
+    ///
+    /// `a>b` has no `=`, so it arrives as a key with an empty value, while
+    /// `a>=b` is split at its `=` into the key `a>` and the value `b`.
+    pub fn take_from(params: &mut HashMap<String, String>) -> Result<Self, String> {
+        let keys: Vec<String> = params.keys().filter(|k| k.contains(['<', '>'])).cloned().collect();
+        let mut clauses = Vec::new();
+        for key in keys {
+            let value = params.remove(&key).unwrap_or_default();
+            let expr = if value.is_empty() { key } else { format!("{key}={value}") };
+            clauses.push(parse_clause(&expr)?);
+        }
+        clauses.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Self { clauses })
+    }
+
+    pub fn matches(&self, doc: &DocStats) -> bool {
+        self.clauses.iter().all(|(field, op, bound)| {
+            doc.fields.get(field)
+                .and_then(|value| value.compare(bound))
+                .is_some_and(|ordering| op.holds(ordering))
+        })
+    }
+
+    pub fn cache_key(&self) -> String {
+        self.clauses.iter()
+            .map(|(field, op, bound)| format!("{field}{op}{bound:?}"))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+fn parse_clause(expr: &str) -> Result<(String, Op, FieldValue), String> {
+    let (pos, op, len) = [(">=", Op::Ge), ("<=", Op::Le), (">", Op::Gt), ("<", Op::Lt)].into_iter()
+        .find_map(|(token, op)| expr.find(token).map(|pos| (pos, op, token.len())))
+        .ok_or_else(|| format!("not a range filter: {expr:?}"))?;
+    let field = expr[..pos].trim();
+    let bound = expr[pos + len..].trim();
+    if field.is_empty() {
+        return Err(format!("range filter without a field: {expr:?}"));
+    }
+    let bound = FieldValue::parse(bound)
+        .ok_or_else(|| format!("range bound must be a date or number: {expr:?}"))?;
+    Ok((field.to_string(), op, bound))
+}
+
+/// How search results are ordered. Score is always the tiebreaker, then path.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    #[default]
+    Score,
+    /// Most recently modified first; documents without a date come last.
+    Date,
+    Path,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score" => Ok(SortOrder::Score),
+            "date" => Ok(SortOrder::Date),
+            "path" => Ok(SortOrder::Path),
+            _ => Err(format!("unknown sort {s:?}, expected score, date or path")),
+        }
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortOrder::Score => "score",
+            SortOrder::Date => "date",
+            SortOrder::Path => "path",
+        })
+    }
+}
+
+impl SortOrder {
+    pub fn sort(self, scored: &mut [(&PathBuf, &DocStats, f32)]) {
+        let by_score = |a: &(&PathBuf, &DocStats, f32), b: &(&PathBuf, &DocStats, f32)| {
+            b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal)
+        };
+        match self {
+            SortOrder::Score => sort_by_score(scored),
+            SortOrder::Date => scored.sort_by(|a, b| {
+                modified(b.1).cmp(&modified(a.1)).then_with(|| by_score(a, b)).then_with(|| a.0.cmp(b.0))
+            }),
+            SortOrder::Path => scored.sort_by(|a, b| a.0.cmp(b.0).then_with(|| by_score(a, b))),
+        }
+    }
+}
+
+fn modified(doc: &DocStats) -> Option<DateTime<Utc>> {
+    match doc.fields.get("modified") {
+        Some(FieldValue::Date(date)) => Some(*date),
+        _ => None,
+    }
+}
@@ -1,13 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
 use xml::reader::{EventReader, XmlEvent};
+use crate::fields::FieldValue;
 use crate::model::DocStats;
 use crate::lexer::Lexer;
 
-pub fn index_document(content: &str) -> DocStats {
-    let chars: Vec<char> = content.chars().collect();
+/// The text of a document plus the `<meta name=.. content=..>` tags found in it.
+pub struct ParsedDocument {
+    pub text: String,
+    pub meta: BTreeMap<String, String>,
+}
+
+pub fn index_document(path: &Path, document: &ParsedDocument) -> DocStats {
+    let chars: Vec<char> = document.text.chars().collect();
     let lexer = Lexer::new(&chars);
     let mut tf = HashMap::new();
     let mut total_words = 0;
@@ -20,29 +28,75 @@ pub fn index_document(content: &str) -> DocStats {
         total_words += 1;
     }
 
-    DocStats { tf, total_words }
+    DocStats {
+        tf,
+        total_words,
+        facets: extract_facets(path, &document.meta),
+        fields: extract_fields(path, &document.meta),
+    }
+}
+
+/// Derives typed fields: `modified` and `size` from the file, plus every
+/// `<meta>` tag whose content is a date or a number, such as a crawl timestamp.
+fn extract_fields(path: &Path, meta: &BTreeMap<String, String>) -> BTreeMap<String, FieldValue> {
+    let mut fields: BTreeMap<String, FieldValue> = meta.iter()
+        .filter_map(|(name, content)| FieldValue::parse(content).map(|value| (name.clone(), value)))
+        .collect();
+    if let Ok(metadata) = fs::metadata(path) {
+        if let Ok(modified) = metadata.modified() {
+            fields.insert("modified".to_string(), FieldValue::Date(modified.into()));
+        }
+        fields.insert("size".to_string(), FieldValue::Number(metadata.len() as f64));
+    }
+    fields
+}
+
+/// Derives facet fields for a document: `category` is its parent directory,
+/// `type` its extension, `modified` the month it was last changed, plus one
+/// field per `<meta>` tag. The derived fields take precedence over tags.
+fn extract_facets(path: &Path, meta: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut facets = meta.clone();
+    if let Some(category) = path.parent().and_then(Path::file_name) {
+        facets.insert("category".to_string(), category.to_string_lossy().into_owned());
+    }
+    if let Some(extension) = path.extension() {
+        facets.insert("type".to_string(), extension.to_string_lossy().to_lowercase());
+    }
+    if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+        let modified: DateTime<Utc> = modified.into();
+        facets.insert("modified".to_string(), modified.format("%Y-%m").to_string());
+    }
+    facets
 }
 
-pub fn parse_xml_file(file_path: &Path) -> io::Result<String> {
+pub fn parse_xml_file(file_path: &Path) -> io::Result<ParsedDocument> {
     let file = fs::File::open(file_path)?;
     let reader = EventReader::new(io::BufReader::new(file));
 
     let mut buffer = String::new();
+    let mut meta = BTreeMap::new();
     for event in reader {
         match event {
             Ok(XmlEvent::Characters(_doc_content)) => {
                 buffer.push_str(&_doc_content);
                 buffer.push(' ');
             }
-            Ok(_) => {}
-            Err(err) => {
-                eprintln!("Failed to read event in {}: {err}", file_path.display());
-                return Err(io::Error::new(io::ErrorKind::Other, err));
+            Ok(XmlEvent::StartElement { name, attributes, .. }) if name.local_name == "meta" => {
+                let attribute = |key: &str| attributes.iter()
+                    .find(|a| a.name.local_name == key)
+                    .map(|a| a.value.trim());
+                if let (Some(key), Some(value)) = (attribute("name"), attribute("content"))
+                    && !key.is_empty() && !value.is_empty()
+                {
+                    meta.insert(key.to_lowercase(), value.to_string());
+                }
             }
+            Ok(_) => {}
+            Err(err) => return Err(io::Error::other(err)),
         }
     }
 
-    Ok(buffer)
+    Ok(ParsedDocument { text: buffer, meta })
 }
 
 pub fn traverse_directory(dir_path: &Path) -> io::Result<Vec<PathBuf>> {
@@ -53,7 +107,7 @@ pub fn traverse_directory(dir_path: &Path) -> io::Result<Vec<PathBuf>> {
             let path = entry.path();
             if path.is_dir() {
                 files.extend(traverse_directory(&path)?);
-            } else if path.extension().map_or(false, |ext| ext == "xhtml") {
+            } else if path.extension().is_some_and(|ext| ext == "xhtml") {
                 files.push(path);
             }
         }
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::fields::FieldValue;
+use crate::lexer::tokenize_query;
+use crate::model::{DocStats, InvertedIndex};
+use crate::ranking::{Ranking, TermStats, compute_tf};
+
+pub fn print_stats(index: &InvertedIndex, files: &[&Path]) {
+    let vocabulary: usize = collection_terms(index).len();
+    let total_words: usize = index.docs.values().map(|d| d.total_words).sum();
+    let avg_len = if index.docs.is_empty() { 0.0 } else { total_words as f64 / index.docs.len() as f64 };
+
+    let built_at = index.meta.built_at.map_or("unknown".to_string(), |t| t.to_rfc3339());
+    println!("Format version:   {}", index.meta.format_version);
+    println!("Built at:         {}", built_at);
+    println!("Documents:        {}", index.docs.len());
+    println!("Vocabulary size:  {}", vocabulary);
+    println!("Total terms:      {}", total_words);
+    println!("Avg doc length:   {:.1} terms", avg_len);
+    for file in files {
+        match fs::metadata(file) {
+            Ok(meta) => println!("Size on disk:     {} ({} bytes)", file.display(), meta.len()),
+            Err(_) => println!("Size on disk:     {} (missing)", file.display()),
+        }
+    }
+}
+
+pub fn print_top_terms(index: &InvertedIndex, top: usize) {
+    let mut terms: Vec<(String, usize, usize)> = collection_terms(index)
+        .into_iter()
+        .map(|(term, (cf, df))| (term, cf, df))
+        .collect();
+    terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    terms.truncate(top);
+
+    println!("{:<30} {:>10} {:>8}", "TERM", "FREQ", "DF");
+    for (term, cf, df) in terms {
+        println!("{:<30} {:>10} {:>8}", term, cf, df);
+    }
+}
+
+pub fn print_doc(index: &InvertedIndex, path: &str) -> bool {
+    let Some((path, doc)) = find_doc(index, path) else {
+        eprintln!("Document not in index: {path}");
+        return false;
+    };
+
+    let mut terms: Vec<(&String, &usize)> = doc.tf.iter().collect();
+    terms.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    println!("{} ({} terms, {} unique)", path.display(), doc.total_words, doc.tf.len());
+    for (field, value) in &doc.facets {
+        println!("  {field}: {value}");
+    }
+    for (field, value) in &doc.fields {
+        match value {
+            FieldValue::Number(n) => println!("  {field} = {n}"),
+            FieldValue::Date(d) => println!("  {field} = {}", d.to_rfc3339()),
+        }
+    }
+    println!("{:<30} {:>8} {:>10}", "TERM", "FREQ", "TF");
+    for (term, freq) in terms {
+        println!("{:<30} {:>8} {:>10.6}", term, freq, compute_tf(term, doc));
+    }
+    true
+}
+
+pub fn print_explain(index: &InvertedIndex, query: &str, path: &str) -> bool {
+    let Some((path, doc)) = find_doc(index, path) else {
+        eprintln!("Document not in index: {path}");
+        return false;
+    };
+
+    let terms = tokenize_query(query);
+    println!("Query terms: {:?}", terms);
+    println!("Document:    {}", path.display());
+    print!("{}", Ranking::default().explain(&terms, doc, &TermStats::new(&terms, index)));
+    true
+}
+
+/// Checks the index for internal inconsistencies and prints every problem found.
+/// Returns whether the index is sound.
+pub fn verify(index: &InvertedIndex) -> bool {
+    let mut problems = 0;
+    let mut report = |msg: String| {
+        eprintln!("{msg}");
+        problems += 1;
+    };
+
+    for (path, doc) in &index.docs {
+        let sum: usize = doc.tf.values().sum();
+        if sum != doc.total_words {
+            report(format!("{}: total_words is {} but term counts sum to {}", path.display(), doc.total_words, sum));
+        }
+        for (term, freq) in &doc.tf {
+            if *freq == 0 {
+                report(format!("{}: term {term:?} has a zero count", path.display()));
+            }
+            if tokenize_query(term).as_slice() != [term.as_str()] {
+                report(format!("{}: term {term:?} is not a normalized token", path.display()));
+            }
+        }
+        if !path.is_file() {
+            report(format!("{}: source file no longer exists", path.display()));
+        }
+    }
+
+    println!("Checked {} documents, {} problem(s) found", index.docs.len(), problems);
+    problems == 0
+}
+
+/// Term -> (collection frequency, document frequency).
+fn collection_terms(index: &InvertedIndex) -> HashMap<String, (usize, usize)> {
+    let mut terms: HashMap<String, (usize, usize)> = HashMap::new();
+    for doc in index.docs.values() {
+        for (term, freq) in &doc.tf {
+            let entry = terms.entry(term.clone()).or_default();
+            entry.0 += freq;
+            entry.1 += 1;
+        }
+    }
+    terms
+}
+
+fn find_doc<'a>(index: &'a InvertedIndex, path: &str) -> Option<(&'a PathBuf, &'a DocStats)> {
+    let wanted = path.replace('\\', "/");
+    let wanted = wanted.strip_prefix("./").unwrap_or(&wanted);
+    index.docs.get_key_value(Path::new(wanted)).or_else(|| {
+        index.docs.iter().find(|(p, _)| p.display().to_string().replace('\\', "/") == wanted)
+    })
+}
@@ -42,3 +42,11 @@ impl<'a> Iterator for Lexer<'a> {
         self.next_token()
     }
 }
+
+/// Splits free text into the same normalized, stopword-free terms the indexer stores.
+pub fn tokenize_query(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    Lexer::new(&chars)
+        .filter(|token| !crate::stopwords::is_stopword(token))
+        .collect()
+}
@@ -1,79 +1,298 @@
+mod analytics;
+mod auth;
+mod cache;
+mod config;
+mod content;
 mod model;
 mod lexer;
+mod logging;
+mod metrics;
+mod eval;
+mod facets;
+mod fields;
 mod indexer;
+mod inspect;
 mod ranking;
 mod server;
 mod stopwords;
+mod storage;
+mod tls;
 
 use std::collections::HashMap;
 use std::env;
-use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::sync::Arc;
+use std::time::Instant;
+use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
+use tracing::{debug, error, info, info_span, warn};
 
-use crate::model::InvertedIndex;
+use crate::config::ServerConfig;
+use crate::logging::LogFormat;
+use crate::model::{IndexMeta, InvertedIndex};
+use crate::ranking::Ranking;
 use crate::indexer::{index_document, parse_xml_file, traverse_directory};
 use crate::server::serve_files;
+use crate::storage::{INDEX_PATH, WAL_PATH, Wal, WalEntry};
+
+const USAGE: &str = "Usage: cargo run [--log-format text|json] <mode> ...
+  cargo run index <folder_path>
+  cargo run add <file_path>...
+  cargo run remove <file_path>...
+  cargo run serve [--config <path>]
+  cargo run stats
+  cargo run terms [--top N]
+  cargo run doc <path>
+  cargo run explain <query> <path>
+  cargo run verify
+  cargo run eval <queries> <qrels> [--k N] [--ranking R] [--compare R]";
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let log_format = match take_option(&mut args, "--log-format").map(|f| f.parse::<LogFormat>()) {
+        None => LogFormat::default(),
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            exit(1);
+        }
+    };
+    logging::init(log_format);
+
     if args.len() < 2 {
-        eprintln!("Usage: \n  cargo run index <folder_path>\n  cargo run serve");
+        eprintln!("{USAGE}");
         exit(1);
     }
 
-    let mode = &args[1];
-
-    if mode == "serve" {
-        let index: InvertedIndex = if let Ok(file) = fs::File::open("index.json") {
-            serde_json::from_reader(io::BufReader::new(file)).unwrap_or_default()
-        } else {
-            eprintln!("index.json not found. Please run indexing first.");
-            InvertedIndex::default()
-        };
-        serve_files(Arc::new(index))?;
-    } else if mode == "index" {
-        if args.len() < 3 {
-             eprintln!("Usage: cargo run index <folder_path>");
-             exit(1);
-        }
-        let directory_path = Path::new(&args[2]);
-        let files = traverse_directory(directory_path)?;
-        
-        println!("Indexing {} files...", files.len());
-        
-        let docs: HashMap<PathBuf, model::DocStats> = files
-            .par_iter()
-            .filter_map(|path| {
-                match parse_xml_file(path) {
-                    Ok(content) => {
-                        println!("Indexing: {}", path.display());
-                        Some((path.clone(), index_document(&content)))
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to parse {}: {}", path.display(), e);
-                        None
+    match args[1].as_str() {
+        "serve" => {
+            let config = match args.get(2).map(String::as_str) {
+                None => ServerConfig::default(),
+                Some("--config") if args.len() == 4 => ServerConfig::load(Path::new(&args[3])).unwrap_or_else(|e| {
+                    error!("Failed to load config: {e}");
+                    exit(1);
+                }),
+                Some(_) => {
+                    eprintln!("Usage: cargo run serve [--config <path>]");
+                    exit(1);
+                }
+            };
+            let started = Instant::now();
+            let index = load_index_or_exit();
+            serve_files(Arc::new(index), started.elapsed(), &config)?;
+        }
+        "index" => {
+            if args.len() < 3 {
+                 eprintln!("Usage: cargo run index <folder_path>");
+                 exit(1);
+            }
+            build_index(Path::new(&args[2]))?;
+        }
+        "add" => {
+            if args.len() < 3 {
+                 eprintln!("Usage: cargo run add <file_path>...");
+                 exit(1);
+            }
+            add_files(&args[2..])?;
+        }
+        "remove" => {
+            if args.len() < 3 {
+                 eprintln!("Usage: cargo run remove <file_path>...");
+                 exit(1);
+            }
+            remove_files(&args[2..])?;
+        }
+        "stats" => {
+            let index = load_index_or_exit();
+            inspect::print_stats(&index, &[Path::new(INDEX_PATH), Path::new(WAL_PATH)]);
+        }
+        "terms" => {
+            let top = match args.get(2).map(String::as_str) {
+                None => 20,
+                Some("--top") => match args.get(3).and_then(|n| n.parse().ok()) {
+                    Some(n) => n,
+                    None => {
+                        eprintln!("Usage: cargo run terms [--top N]");
+                        exit(1);
                     }
+                },
+                Some(_) => {
+                    eprintln!("Usage: cargo run terms [--top N]");
+                    exit(1);
                 }
-            })
-            .collect();
+            };
+            inspect::print_top_terms(&load_index_or_exit(), top);
+        }
+        "doc" => {
+            if args.len() < 3 {
+                 eprintln!("Usage: cargo run doc <path>");
+                 exit(1);
+            }
+            if !inspect::print_doc(&load_index_or_exit(), &args[2]) {
+                exit(1);
+            }
+        }
+        "explain" => {
+            if args.len() < 4 {
+                 eprintln!("Usage: cargo run explain <query> <path>");
+                 exit(1);
+            }
+            if !inspect::print_explain(&load_index_or_exit(), &args[2], &args[3]) {
+                exit(1);
+            }
+        }
+        "verify" => {
+            if !inspect::verify(&load_index_or_exit()) {
+                exit(1);
+            }
+        }
+        "eval" => {
+            if args.len() < 4 {
+                 eprintln!("Usage: cargo run eval <queries> <qrels> [--k N] [--ranking R] [--compare R]");
+                 exit(1);
+            }
+            run_eval(&args[2], &args[3], &args[4..])?;
+        }
+        mode => {
+            eprintln!("Unknown mode: {}", mode);
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    }
 
-        let index = InvertedIndex { docs };
+    Ok(())
+}
 
-        println!("\n--- Summary ---");
-        println!("Total documents indexed: {}", index.docs.len());
+fn load_index_or_exit() -> InvertedIndex {
+    match storage::open_index(Path::new(INDEX_PATH), Path::new(WAL_PATH)) {
+        Ok(index) => index,
+        Err(e) => {
+            error!("Failed to load index: {e}. Please run indexing first.");
+            exit(1);
+        }
+    }
+}
 
-        let file = fs::File::create("index.json")?;
-        serde_json::to_writer_pretty(file, &index)?;
-        println!("Index saved to index.json");
-    } else {
-        eprintln!("Unknown mode: {}", mode);
-        eprintln!("Usage: \n  cargo run index <folder_path>\n  cargo run serve");
+/// Removes `name <value>` from `args` and returns the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let pos = args.iter().position(|a| a == name)?;
+    if pos + 1 >= args.len() {
+        eprintln!("Missing value for {name}");
         exit(1);
     }
+    let value = args.remove(pos + 1);
+    args.remove(pos);
+    Some(value)
+}
+
+fn build_index(directory_path: &Path) -> io::Result<()> {
+    let _span = info_span!("index", dir = %directory_path.display()).entered();
+    let started = Instant::now();
+    let files = traverse_directory(directory_path)?;
+    
+    info!(files = files.len(), "Indexing files");
+
+    // hidden automatically when stderr is not a terminal, e.g. in CI
+    let progress = ProgressBar::new(files.len() as u64).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} files ({per_sec}, eta {eta})").unwrap()
+    );
+
+    let parse_span = info_span!("parse");
+    let docs: HashMap<PathBuf, model::DocStats> = files
+        .par_iter()
+        .progress_with(progress.clone())
+        .filter_map(|path| {
+            let _span = parse_span.enter();
+            match parse_xml_file(path) {
+                Ok(document) => {
+                    debug!(path = %path.display(), "Indexed document");
+                    Some((path.clone(), index_document(path, &document)))
+                }
+                Err(e) => {
+                    progress.suspend(|| warn!(path = %path.display(), error = %e, "Failed to parse"));
+                    None
+                }
+            }
+        })
+        .collect();
+    progress.finish_and_clear();
+
+    let index = InvertedIndex { meta: IndexMeta::new(directory_path.to_path_buf()), docs };
+    info!(
+        documents = index.docs.len(),
+        failed = files.len() - index.docs.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Indexing finished",
+    );
+
+    info_span!("save").in_scope(|| -> io::Result<()> {
+        storage::save_index(Path::new(INDEX_PATH), &index)?;
+        // The fresh snapshot supersedes anything ingested since the last one.
+        storage::reset_wal(Path::new(WAL_PATH))
+    })?;
+    info!(path = INDEX_PATH, "Index saved");
+    Ok(())
+}
+
+fn add_files(paths: &[String]) -> io::Result<()> {
+    let mut wal = Wal::open(Path::new(WAL_PATH))?;
+    for arg in paths {
+        let path = PathBuf::from(arg);
+        match parse_xml_file(&path) {
+            Ok(document) => {
+                let stats = index_document(&path, &document);
+                wal.append(&WalEntry::Put { path: path.clone(), stats })?;
+                info!(path = %path.display(), "Added document to WAL");
+            }
+            Err(e) => warn!(path = %path.display(), error = %e, "Failed to parse"),
+        }
+    }
+    Ok(())
+}
+
+fn remove_files(paths: &[String]) -> io::Result<()> {
+    let mut wal = Wal::open(Path::new(WAL_PATH))?;
+    for arg in paths {
+        let path = PathBuf::from(arg);
+        wal.append(&WalEntry::Delete { path: path.clone() })?;
+        info!(path = %path.display(), "Removed document via WAL");
+    }
+    Ok(())
+}
+
+fn run_eval(queries_path: &str, qrels_path: &str, options: &[String]) -> io::Result<()> {
+    let mut k = 10;
+    let mut ranking = Ranking::default();
+    let mut compare = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = options.next().map(String::as_str).unwrap_or("");
+        let parsed = match option.as_str() {
+            "--k" => value.parse().ok().filter(|&v| v > 0).map(|v| k = v)
+                .ok_or_else(|| format!("--k must be a positive integer, got {value:?}")),
+            "--ranking" => value.parse().map(|v| ranking = v),
+            "--compare" => value.parse().map(|v| compare = Some(v)),
+            _ => Err(format!("unknown option {option}")),
+        };
+        if let Err(e) = parsed {
+            eprintln!("{e}");
+            exit(1);
+        }
+    }
 
+    let queries = eval::load_queries(Path::new(queries_path))?;
+    let qrels = eval::load_qrels(Path::new(qrels_path))?;
+    let index = load_index_or_exit();
+
+    let results = eval::evaluate(&index, &queries, &qrels, ranking, k);
+    match compare {
+        Some(other) => {
+            let other_results = eval::evaluate(&index, &queries, &qrels, other, k);
+            eval::print_comparison(&results, &other_results, ranking, other, k);
+        }
+        None => eval::print_report(&results, ranking, k),
+    }
     Ok(())
 }
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use crate::cache::CacheStats;
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// Server metrics, rendered in the Prometheus text exposition format.
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(&'static str, u16), u64>>,
+    latency: Mutex<BTreeMap<&'static str, Histogram>>,
+    index_documents: AtomicU64,
+    index_size_bytes: AtomicU64,
+    index_load_seconds: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            requests: Mutex::new(BTreeMap::new()),
+            latency: Mutex::new(BTreeMap::new()),
+            index_documents: AtomicU64::new(0),
+            index_size_bytes: AtomicU64::new(0),
+            index_load_seconds: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe_request(&self, route: &'static str, status: u16, elapsed: Duration) {
+        *lock(&self.requests).entry((route, status)).or_default() += 1;
+        lock(&self.latency).entry(route).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    pub fn set_index(&self, documents: usize, size_bytes: u64, load_time: Duration) {
+        self.index_documents.store(documents as u64, Ordering::Relaxed);
+        self.index_size_bytes.store(size_bytes, Ordering::Relaxed);
+        self.index_load_seconds.store(load_time.as_secs_f64().to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn render(&self, cache: CacheStats) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP search_http_requests_total HTTP requests handled, by route and status.\n");
+        out.push_str("# TYPE search_http_requests_total counter\n");
+        for ((route, status), count) in lock(&self.requests).iter() {
+            let _ = writeln!(out, "search_http_requests_total{{route=\"{route}\",status=\"{status}\"}} {count}");
+        }
+
+        out.push_str("# HELP search_http_request_duration_seconds Time spent handling HTTP requests, by route.\n");
+        out.push_str("# TYPE search_http_request_duration_seconds histogram\n");
+        for (route, histogram) in lock(&self.latency).iter() {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(out, "search_http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{bound}\"}} {count}");
+            }
+            let _ = writeln!(out, "search_http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}", histogram.count);
+            let _ = writeln!(out, "search_http_request_duration_seconds_sum{{route=\"{route}\"}} {}", histogram.sum);
+            let _ = writeln!(out, "search_http_request_duration_seconds_count{{route=\"{route}\"}} {}", histogram.count);
+        }
+
+        gauge(&mut out, "search_index_documents", "Documents in the served index.",
+            self.index_documents.load(Ordering::Relaxed) as f64);
+        gauge(&mut out, "search_index_size_bytes", "Size of the index snapshot and WAL on disk.",
+            self.index_size_bytes.load(Ordering::Relaxed) as f64);
+        gauge(&mut out, "search_index_load_seconds", "Time taken to load the served index.",
+            f64::from_bits(self.index_load_seconds.load(Ordering::Relaxed)));
+
+        counter(&mut out, "search_cache_hits_total", "Search requests answered from the query cache.", cache.hits);
+        counter(&mut out, "search_cache_misses_total", "Search requests not found in the query cache.", cache.misses);
+        counter(&mut out, "search_cache_evictions_total", "Cache entries evicted to stay under max_entries.", cache.evictions);
+        counter(&mut out, "search_cache_expirations_total", "Cache entries dropped after their TTL.", cache.expirations);
+        counter(&mut out, "search_cache_invalidations_total", "Times the cache was cleared because the index changed.", cache.invalidations);
+        gauge(&mut out, "search_cache_entries", "Entries currently in the query cache.", cache.entries as f64);
+        let lookups = cache.hits + cache.misses;
+        let hit_ratio = if lookups == 0 { 0.0 } else { cache.hits as f64 / lookups as f64 };
+        gauge(&mut out, "search_cache_hit_ratio", "Fraction of cache lookups that were hits.", hit_ratio);
+
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
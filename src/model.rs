@@ -1,14 +1,71 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use crate::fields::FieldValue;
+
+/// Bumped whenever the on-disk layout of `InvertedIndex` changes.
+pub const INDEX_FORMAT_VERSION: u32 = 3;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct DocStats {
     pub tf: HashMap<String, usize>,
     pub total_words: usize,
+    /// Metadata used to narrow and group results, e.g. `category` and `type`.
+    /// Empty for documents indexed before format version 2.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub facets: BTreeMap<String, String>,
+    /// Typed values for range filters and sorting, e.g. `modified` and `size`.
+    /// Empty for documents indexed before format version 3.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, FieldValue>,
+}
+
+/// Describes how terms were produced, so a server can report what it is serving.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnalyzerInfo {
+    pub tokenizer: String,
+    pub case: String,
+    pub stopwords: usize,
+    pub extensions: Vec<String>,
+}
+
+impl Default for AnalyzerInfo {
+    fn default() -> Self {
+        Self {
+            tokenizer: "alphanumeric runs".to_string(),
+            case: "uppercase".to_string(),
+            stopwords: crate::stopwords::count(),
+            extensions: vec!["xhtml".to_string()],
+        }
+    }
+}
+
+/// Build information stored alongside the documents. Indexes written before
+/// this existed load with `format_version` 0 and no build time.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct IndexMeta {
+    pub format_version: u32,
+    pub built_at: Option<DateTime<Utc>>,
+    pub source: Option<PathBuf>,
+    pub analyzer: AnalyzerInfo,
+}
+
+impl IndexMeta {
+    pub fn new(source: PathBuf) -> Self {
+        Self {
+            format_version: INDEX_FORMAT_VERSION,
+            built_at: Some(Utc::now()),
+            source: Some(source),
+            analyzer: AnalyzerInfo::default(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct InvertedIndex {
+    #[serde(default)]
+    pub meta: IndexMeta,
     pub docs: HashMap<PathBuf, DocStats>,
 }
@@ -1,3 +1,8 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use serde::Serialize;
 use crate::model::{DocStats, InvertedIndex};
 
 pub fn compute_tf(term: &str, doc: &DocStats) -> f32 {
@@ -7,8 +12,228 @@ pub fn compute_tf(term: &str, doc: &DocStats) -> f32 {
     f / n
 }
 
-pub fn compute_idf(term: &str, index: &InvertedIndex) -> f32 {
-    let n = index.docs.len() as f32;
-    let m = index.docs.values().filter(|stats| stats.tf.contains_key(term)).count() as f32;
-    (n / (1.0 + m)).log10()
+pub fn document_frequency(term: &str, index: &InvertedIndex) -> usize {
+    index.docs.values().filter(|stats| stats.tf.contains_key(term)).count()
+}
+
+/// Collection statistics for the terms of one query, computed once per search.
+pub struct TermStats {
+    pub docs: usize,
+    pub avg_len: f32,
+    pub df: HashMap<String, usize>,
+}
+
+impl TermStats {
+    pub fn new(terms: &[String], index: &InvertedIndex) -> Self {
+        let df = terms.iter().map(|t| (t.clone(), document_frequency(t, index))).collect();
+        let total_words: usize = index.docs.values().map(|d| d.total_words).sum();
+        let avg_len = if index.docs.is_empty() { 0.0 } else { total_words as f32 / index.docs.len() as f32 };
+        Self { docs: index.docs.len(), avg_len, df }
+    }
+
+    pub fn df(&self, term: &str) -> usize {
+        self.df.get(term).copied().unwrap_or(0)
+    }
+
+    pub fn idf(&self, term: &str) -> f32 {
+        (self.docs as f32 / (1.0 + self.df(term) as f32)).log10()
+    }
+}
+
+/// The scoring function used to rank documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Ranking {
+    #[default]
+    TfIdf,
+    Bm25 { k1: f32, b: f32 },
+}
+
+impl Ranking {
+    pub fn score(&self, terms: &[String], doc: &DocStats, stats: &TermStats) -> f32 {
+        match *self {
+            Ranking::TfIdf => terms.iter()
+                .map(|term| compute_tf(term, doc) * stats.idf(term))
+                .sum(),
+            Ranking::Bm25 { k1, b } => terms.iter()
+                .map(|term| bm25_tf(term, doc, stats, k1, b) * bm25_idf(term, stats))
+                .sum(),
+        }
+    }
+
+    /// Explains `self.score(terms, doc, stats)` term by term. The root value equals the score.
+    pub fn explain(&self, terms: &[String], doc: &DocStats, stats: &TermStats) -> Explanation {
+        let details: Vec<Explanation> = terms.iter().map(|term| match *self {
+            Ranking::TfIdf => explain_tfidf(term, doc, stats),
+            Ranking::Bm25 { k1, b } => explain_bm25(term, doc, stats, k1, b),
+        }).collect();
+
+        Explanation {
+            value: details.iter().map(|d| d.value).sum(),
+            description: format!("{self}, sum of:"),
+            details,
+        }
+    }
+}
+
+impl fmt::Display for Ranking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ranking::TfIdf => write!(f, "tfidf"),
+            Ranking::Bm25 { k1, b } => write!(f, "bm25:k1={k1},b={b}"),
+        }
+    }
+}
+
+impl FromStr for Ranking {
+    type Err = String;
+
+    /// Parses `tfidf`, `bm25` or `bm25:k1=<f>,b=<f>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        match name {
+            "tfidf" if params.is_empty() => Ok(Ranking::TfIdf),
+            "bm25" => {
+                let (mut k1, mut b) = (1.2, 0.75);
+                for param in params.split(',').filter(|p| !p.is_empty()) {
+                    let (key, value) = param.split_once('=').ok_or_else(|| format!("bad parameter {param:?}"))?;
+                    let value: f32 = value.parse().map_err(|_| format!("bad value for {key}: {value:?}"))?;
+                    match key {
+                        "k1" => k1 = value,
+                        "b" => b = value,
+                        _ => return Err(format!("unknown bm25 parameter {key:?}")),
+                    }
+                }
+                Ok(Ranking::Bm25 { k1, b })
+            }
+            _ => Err(format!("unknown ranking {s:?}, expected tfidf or bm25[:k1=..,b=..]")),
+        }
+    }
+}
+
+fn bm25_tf(term: &str, doc: &DocStats, stats: &TermStats, k1: f32, b: f32) -> f32 {
+    let f = doc.tf.get(term).copied().unwrap_or(0) as f32;
+    if f == 0.0 { return 0.0; }
+    f * (k1 + 1.0) / (f + k1 * bm25_length_norm(doc, stats, b))
+}
+
+fn bm25_length_norm(doc: &DocStats, stats: &TermStats, b: f32) -> f32 {
+    let relative_len = if stats.avg_len == 0.0 { 1.0 } else { doc.total_words as f32 / stats.avg_len };
+    1.0 - b + b * relative_len
+}
+
+fn bm25_idf(term: &str, stats: &TermStats) -> f32 {
+    let n = stats.docs as f32;
+    let df = stats.df(term) as f32;
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+}
+
+/// Scores every document and returns the best `limit`, highest score first.
+pub fn rank<'a>(
+    index: &'a InvertedIndex,
+    terms: &[String],
+    ranking: Ranking,
+    stats: &TermStats,
+    limit: usize,
+) -> Vec<(&'a PathBuf, &'a DocStats, f32)> {
+    let mut scored = matches(index, terms, ranking, stats);
+    sort_by_score(&mut scored);
+    scored.truncate(limit);
+    scored
+}
+
+/// Every document with a positive score, in no particular order.
+pub fn matches<'a>(
+    index: &'a InvertedIndex,
+    terms: &[String],
+    ranking: Ranking,
+    stats: &TermStats,
+) -> Vec<(&'a PathBuf, &'a DocStats, f32)> {
+    index.docs.iter()
+        .map(|(path, doc)| (path, doc, ranking.score(terms, doc, stats)))
+        .filter(|(_, _, score)| *score > 0.0)
+        .collect()
+}
+
+/// Orders by descending score; ties are broken by path so rankings are reproducible.
+pub fn sort_by_score(scored: &mut [(&PathBuf, &DocStats, f32)]) {
+    scored.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(b.0)));
+}
+
+/// A node in the breakdown of how a score was computed.
+#[derive(Serialize, Debug)]
+pub struct Explanation {
+    pub value: f32,
+    pub description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn leaf(value: f32, description: String) -> Self {
+        Self { value, description, details: Vec::new() }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(f, "{:indent$}{:.6} = {}", "", self.value, self.description, indent = depth * 2)?;
+        for detail in &self.details {
+            detail.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+fn explain_tfidf(term: &str, doc: &DocStats, stats: &TermStats) -> Explanation {
+    let freq = doc.tf.get(term).copied().unwrap_or(0);
+    let tf = compute_tf(term, doc);
+    let idf = stats.idf(term);
+    let norm = if doc.total_words == 0 { 0.0 } else { 1.0 / doc.total_words as f32 };
+    Explanation {
+        value: tf * idf,
+        description: format!("weight({term}), tf * idf"),
+        details: vec![
+            Explanation {
+                value: tf,
+                description: "tf, freq * length_norm".to_string(),
+                details: vec![
+                    Explanation::leaf(freq as f32, "freq, occurrences in document".to_string()),
+                    Explanation::leaf(norm, format!("length_norm, 1 / total_words={}", doc.total_words)),
+                ],
+            },
+            Explanation::leaf(idf, format!("idf, log10(docs={} / (1 + df={}))", stats.docs, stats.df(term))),
+        ],
+    }
+}
+
+fn explain_bm25(term: &str, doc: &DocStats, stats: &TermStats, k1: f32, b: f32) -> Explanation {
+    let freq = doc.tf.get(term).copied().unwrap_or(0);
+    let tf = bm25_tf(term, doc, stats, k1, b);
+    let idf = bm25_idf(term, stats);
+    let norm = bm25_length_norm(doc, stats, b);
+    Explanation {
+        value: tf * idf,
+        description: format!("weight({term}), tf * idf"),
+        details: vec![
+            Explanation {
+                value: tf,
+                description: format!("tf, freq * (k1 + 1) / (freq + k1 * length_norm), k1={k1}"),
+                details: vec![
+                    Explanation::leaf(freq as f32, "freq, occurrences in document".to_string()),
+                    Explanation::leaf(norm, format!(
+                        "length_norm, 1 - b + b * total_words={} / avg_len={:.1}, b={b}",
+                        doc.total_words, stats.avg_len,
+                    )),
+                ],
+            },
+            Explanation::leaf(idf, format!(
+                "idf, ln(1 + (docs={} - df={} + 0.5) / (df + 0.5))",
+                stats.docs, stats.df(term),
+            )),
+        ],
+    }
 }
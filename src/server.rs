@@ -1,141 +1,690 @@
 use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::path::Path;
-use std::sync::Arc;
-use tiny_http::{Header, Response, Server};
-use crate::model::InvertedIndex;
-use crate::lexer::Lexer;
-use crate::ranking::{compute_idf, compute_tf};
-
-pub fn serve_files(index: Arc<InvertedIndex>) -> io::Result<()> {
-    let server = Server::http("0.0.0.0:6969").unwrap();
-    println!("Server running on http://localhost:6969");
-
-    for request in server.incoming_requests() {
-        let url = request.url().to_string();
-        
-        if url == "/" {
-            let content = fs::read_to_string("index.html").unwrap_or_else(|_| "<h1>Index.html not found</h1>".to_string());
-            let response = Response::from_string(content).with_header(
-                Header::from_bytes("Content-Type", "text/html").unwrap()
-            );
-            request.respond(response)?;
-            continue;
+use std::panic;
+use std::path::{Component, Path};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::ser::{SerializeTuple, Serializer};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, error, info, info_span, warn};
+use crate::analytics::{Event, EventLog};
+use crate::auth::{AuthError, Authenticator, RateLimiter, Scope, allowed_origin};
+use crate::cache::QueryCache;
+use crate::config::{CorsConfig, ServerConfig};
+use crate::content::{self, EmbeddedAsset, Encoding};
+use crate::facets::{self, FacetCounts, FacetFilter};
+use crate::fields::{RangeFilter, SortOrder};
+use crate::metrics::Metrics;
+use crate::storage;
+use crate::tls::{self, TlsMaterial};
+use crate::model::{IndexMeta, InvertedIndex};
+use crate::lexer::tokenize_query;
+use crate::ranking::{Explanation, Ranking, TermStats, matches};
+
+type HttpResponse = Response<io::Cursor<Vec<u8>>>;
+
+/// The index currently being served. Swapped as a whole on reload, so a request
+/// that grabbed it keeps a consistent view even if a reload happens meanwhile.
+struct ServedIndex {
+    index: Arc<InvertedIndex>,
+    /// Bumped whenever the served index changes; cached results from older generations are dropped.
+    generation: u64,
+    loaded_at: DateTime<Utc>,
+}
+
+struct AppState {
+    served: RwLock<Arc<ServedIndex>>,
+    reload_lock: Mutex<()>,
+    shutting_down: AtomicBool,
+    request_id_prefix: u32,
+    next_request_id: AtomicU64,
+    events: EventLog,
+    metrics: Metrics,
+    cache: QueryCache<Arc<CachedSearch>>,
+    auth: Authenticator,
+    limiter: RateLimiter,
+    cors: CorsConfig,
+}
+
+impl AppState {
+    fn current(&self) -> Arc<ServedIndex> {
+        self.served.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Loads the index from disk and swaps it in. On failure the old index keeps serving.
+    fn reload(&self) -> io::Result<Arc<ServedIndex>> {
+        let _guard = self.reload_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let started = Instant::now();
+        let index = storage::open_index(Path::new(storage::INDEX_PATH), Path::new(storage::WAL_PATH))?;
+        if index.docs.is_empty() && !self.current().index.docs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "refusing to replace the served index with an empty one"));
         }
 
-        if url.starts_with("/api/search") {
-            let query_string = url.split('?').nth(1).unwrap_or("");
-            let mut search_terms = Vec::new();
-
-            for pair in query_string.split('&') {
-                let mut parts = pair.split('=');
-                if let Some(key) = parts.next() {
-                    if key == "q" {
-                        if let Some(value) = parts.next() {
-                            let decoded = value.replace("+", " ").replace("%20", " ");
-                            let chars: Vec<char> = decoded.chars().collect();
-                            let lexer = Lexer::new(&chars);
-                            for token in lexer {
-                                if !crate::stopwords::is_stopword(&token) {
-                                    search_terms.push(token);
-                                }
-                            }
+        let served = Arc::new(ServedIndex {
+            index: Arc::new(index),
+            generation: self.current().generation + 1,
+            loaded_at: Utc::now(),
+        });
+        *self.served.write().unwrap_or_else(|e| e.into_inner()) = served.clone();
+        self.metrics.set_index(served.index.docs.len(), storage::size_on_disk(), started.elapsed());
+        info!(documents = served.index.docs.len(), generation = served.generation, "Index reloaded");
+        Ok(served)
+    }
+}
+
+/// A serialized search response kept in the query cache.
+struct CachedSearch {
+    body: String,
+    etag: String,
+    /// Documents matched, before the response was cut to a page.
+    total: usize,
+    results: Vec<String>,
+}
+
+/// Serves until SIGTERM or SIGINT, then stops accepting requests and waits for
+/// in-flight ones to finish. SIGHUP reloads the index from disk.
+pub fn serve_files(index: Arc<InvertedIndex>, load_time: Duration, config: &ServerConfig) -> io::Result<()> {
+    let metrics = Metrics::new();
+    metrics.set_index(index.docs.len(), storage::size_on_disk(), load_time);
+    let state = AppState {
+        served: RwLock::new(Arc::new(ServedIndex { index, generation: 0, loaded_at: Utc::now() })),
+        reload_lock: Mutex::new(()),
+        shutting_down: AtomicBool::new(false),
+        request_id_prefix: Utc::now().timestamp() as u32,
+        next_request_id: AtomicU64::new(0),
+        events: EventLog::open(&config.query_log)?,
+        metrics,
+        cache: QueryCache::new(&config.cache),
+        auth: Authenticator::new(config.auth.clone()),
+        limiter: RateLimiter::new(config.rate_limit.clone()),
+        cors: config.cors.clone(),
+    };
+
+    let terminate = Arc::new(AtomicBool::new(false));
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, terminate.clone())?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, terminate.clone())?;
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, reload.clone())?;
+
+    let mut tls = config.tls.as_ref().map(TlsMaterial::load).transpose()?;
+    let listener = Listener { server: RwLock::new(Some(Arc::new(bind(&config.addr, tls.as_ref())?))) };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    info!(addr = %config.addr, workers = config.workers, "Server running on {scheme}://{}", config.addr);
+
+    thread::scope(|scope| {
+        for _ in 0..config.workers.max(1) {
+            scope.spawn(|| worker_loop(&listener, &state));
+        }
+
+        let mut last_tls_check = Instant::now();
+        let mut seen_tls_change = tls.as_ref().and_then(|t| t.modified);
+        while !terminate.load(Ordering::Relaxed) {
+            thread::sleep(POLL_INTERVAL);
+            if reload.swap(false, Ordering::Relaxed)
+                && let Err(e) = state.reload()
+            {
+                error!(error = %e, "Index reload failed, keeping the current index");
+            }
+
+            if let (Some(tls_config), Some(current)) = (&config.tls, &tls)
+                && last_tls_check.elapsed() >= Duration::from_secs(tls_config.reload_check_secs)
+            {
+                last_tls_check = Instant::now();
+                let modified = tls::modified(tls_config);
+                if modified.is_none() || modified == seen_tls_change {
+                    continue;
+                }
+                seen_tls_change = modified;
+                match TlsMaterial::load(tls_config) {
+                    Ok(material) => match listener.rebind(&state, &config.addr, &material, current) {
+                        Ok(()) => {
+                            info!("TLS certificate reloaded");
+                            tls = Some(material);
                         }
-                    }
+                        Err(e) => error!(error = %e, "Failed to apply new TLS certificate, keeping the current one"),
+                    },
+                    Err(e) => error!(error = %e, "New TLS certificate is invalid, keeping the current one"),
                 }
             }
-            
-            println!("Searching for: {:?}", search_terms);
+        }
 
-            let mut results: Vec<(String, f32, String)> = Vec::new();
-            
-            // precompute
-            let idfs: HashMap<String, f32> = search_terms.iter().map(|t| {
-                (t.clone(), compute_idf(t, &index))
-            }).collect();
-
-            for (path, stats) in &index.docs {
-                let mut score = 0.0;
-                let mut first_match = None;
-                for term in &search_terms {
-                    let tf = compute_tf(term, stats);
-                    let idf = *idfs.get(term).unwrap_or(&0.0);
-                    score += tf * idf;
-                    
-                    if first_match.is_none() && stats.tf.contains_key(term) {
-                        first_match = Some(term.clone());
+        info!("Shutting down, waiting for in-flight requests");
+        state.shutting_down.store(true, Ordering::Relaxed);
+        if let Some(server) = listener.current() {
+            server.unblock();
+        }
+    });
+
+    info!("Server stopped");
+    Ok(())
+}
+
+/// How often workers and the signal loop check for shutdown and reload requests.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The bound server, swappable so HTTPS can pick up a renewed certificate.
+struct Listener {
+    server: RwLock<Option<Arc<Server>>>,
+}
+
+impl Listener {
+    fn current(&self) -> Option<Arc<Server>> {
+        self.server.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replaces the server with one using `material`. tiny_http cannot change the
+    /// certificate of a running server, so the port is released and bound again;
+    /// connections attempted during that window are refused. Both certificates
+    /// were validated when loaded, so binding only fails for reasons such as the
+    /// port being taken; then the server is rebound with `fallback`, retrying
+    /// until that succeeds, so the listener is never left without a server.
+    fn rebind(&self, state: &AppState, addr: &str, material: &TlsMaterial, fallback: &TlsMaterial) -> io::Result<()> {
+        let old = self.server.write().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(mut old) = old {
+            // finish requests the old server already accepted
+            while let Ok(Some(request)) = old.try_recv() {
+                handle_request(state, request);
+            }
+            // workers hold a clone for at most one poll interval
+            loop {
+                match Arc::try_unwrap(old) {
+                    Ok(server) => {
+                        drop(server);
+                        break;
                     }
-                }
-                
-                if score > 0.0 {
-                    let mut snippet = String::new();
-                    if let Some(term) = first_match {
-                        if let Ok(content) = fs::read_to_string(path) {
-                            let content_upper = content.to_uppercase();
-                            if let Some(pos) = content_upper.find(&term) {
-                                // char boundaries
-                                let char_pos = content[..pos].chars().count();
-                                
-                                let start_char = char_pos.saturating_sub(15);
-                                let end_char = (char_pos + term.chars().count() + 40).min(content.chars().count());
-                                
-                                snippet = content.chars().skip(start_char).take(end_char - start_char).collect::<String>();
-                                snippet = snippet.replace('\n', " ").trim().to_string();
-                                
-                                if start_char > 0 { snippet = format!("...{}", snippet); }
-                                if end_char < content.chars().count() { snippet = format!("{}...", snippet); }
-                            }
-                        }
+                    Err(server) => {
+                        old = server;
+                        thread::sleep(Duration::from_millis(10));
                     }
-                    results.push((path.display().to_string().replace('\\', "/"), score, snippet));
                 }
             }
+        }
 
-            results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            results.truncate(20);
+        let (server, result) = match bind(addr, Some(material)) {
+            Ok(server) => (server, Ok(())),
+            Err(e) => (bind_until_bound(addr, fallback), Err(e)),
+        };
+        *self.server.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(server));
+        result
+    }
+}
 
-            let json_results = serde_json::to_string(&results)?;
-            let response = Response::from_string(json_results)
-                .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
-                .with_header(Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap());
-            
-            request.respond(response)?;
+/// Binds `addr`, retrying briefly while a just-closed listener releases the port.
+fn bind(addr: &str, tls: Option<&TlsMaterial>) -> io::Result<Server> {
+    let mut attempts = 0;
+    loop {
+        let result = match tls {
+            // material is validated on load; a panic here would leave no server at all
+            Some(material) => panic::catch_unwind(|| Server::https(addr, material.ssl_config()))
+                .unwrap_or_else(|_| Err("tiny_http rejected the TLS certificate".into())),
+            None => Server::http(addr),
+        };
+        match result {
+            Ok(server) => return Ok(server),
+            Err(e) if attempts < 40 && e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::AddrInUse) => {
+                attempts += 1;
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(io::Error::other(e)),
+        }
+    }
+}
+
+/// Binds `addr` with `tls`, retrying every second for as long as it takes.
+fn bind_until_bound(addr: &str, tls: &TlsMaterial) -> Server {
+    loop {
+        match bind(addr, Some(tls)) {
+            Ok(server) => return server,
+            Err(e) => {
+                error!(error = %e, "Failed to rebind with the current TLS certificate, retrying");
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+fn worker_loop(listener: &Listener, state: &AppState) {
+    while !state.shutting_down.load(Ordering::Relaxed) {
+        let Some(server) = listener.current() else {
+            thread::sleep(POLL_INTERVAL);
             continue;
+        };
+        match server.recv_timeout(POLL_INTERVAL) {
+            Ok(Some(request)) => handle_request(state, request),
+            Ok(None) => {}
+            Err(e) => {
+                error!(error = %e, "Failed to accept request");
+                thread::sleep(POLL_INTERVAL);
+            }
         }
+    }
+}
+
+fn handle_request(state: &AppState, request: Request) {
+    let request_id = header_value(&request, "X-Request-Id")
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            let n = state.next_request_id.fetch_add(1, Ordering::Relaxed);
+            format!("{:08x}-{n:06x}", state.request_id_prefix)
+        });
+    let span = info_span!("request", id = %request_id, method = %request.method(), url = %request.url());
+    let _guard = span.enter();
+
+    let started = Instant::now();
+    let route = route_of(request.url());
+    let response = if *request.method() == Method::Options {
+        // CORS preflight; browsers never send credentials with it
+        Response::from_string("").with_status_code(204)
+            .with_header(Header::from_bytes("Access-Control-Allow-Methods", "GET, POST, OPTIONS").unwrap())
+            .with_header(Header::from_bytes("Access-Control-Allow-Headers", "Authorization, X-API-Key, Content-Type").unwrap())
+    } else {
+        match admit(state, &request, route) {
+            Ok(()) => handle(state, &request, route),
+            Err(rejection) => rejection,
+        }
+    };
+    let response = with_cors(&state.cors, &request, response);
+    let status = response.status_code().0;
+    state.metrics.observe_request(route, status, started.elapsed());
+    info!(status, elapsed_ms = started.elapsed().as_secs_f64() * 1000.0, "Request handled");
+
+    let response = response.with_header(Header::from_bytes("X-Request-Id", request_id.as_bytes()).unwrap());
+    if let Err(e) = request.respond(response) {
+        warn!(error = %e, "Failed to send response");
+    }
+}
+
+/// Authenticates the caller and applies its rate limit, returning the rejection response on failure.
+fn admit(state: &AppState, request: &Request, route: &str) -> Result<(), HttpResponse> {
+    let required = match route {
+        "/healthz" | "/readyz" => return Ok(()),
+        "/admin/reload" => Scope::Admin,
+        _ => Scope::Read,
+    };
+    let key = header_value(request, "X-API-Key").or_else(|| {
+        header_value(request, "Authorization").and_then(|v| v.strip_prefix("Bearer "))
+    });
+    let remote = request.remote_addr().map_or("unknown".to_string(), |addr| addr.ip().to_string());
+
+    let client = state.auth.authorize(key, required, &remote).map_err(|e| match e {
+        AuthError::Unauthorized => Response::from_string("Missing or invalid API key").with_status_code(401)
+            .with_header(Header::from_bytes("WWW-Authenticate", "Bearer").unwrap()),
+        AuthError::Forbidden => Response::from_string("API key lacks the required scope").with_status_code(403),
+    })?;
+
+    state.limiter.check(&client).map_err(|retry_after| {
+        warn!(client = %client.id, "Rate limited");
+        Response::from_string("Too Many Requests").with_status_code(429)
+            .with_header(Header::from_bytes("Retry-After", retry_after.as_secs_f64().ceil().to_string()).unwrap())
+    })
+}
+
+fn with_cors(cors: &CorsConfig, request: &Request, response: HttpResponse) -> HttpResponse {
+    let origin = header_value(request, "Origin");
+    match allowed_origin(cors, origin) {
+        Some("*") => response.with_header(Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap()),
+        Some(origin) => response
+            .with_header(Header::from_bytes("Access-Control-Allow-Origin", origin).unwrap())
+            .with_header(Header::from_bytes("Vary", "Origin").unwrap()),
+        None => response,
+    }
+}
 
-        if url.starts_with("/docs/") {
-            let file_path_str = &url["/docs/".len()..];
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+const ROUTES: &[&str] = &["/", "/healthz", "/readyz", "/metrics", "/api/search", "/api/click", "/api/info", "/admin/reload"];
+
+/// Maps a request URL to the route label used for dispatch, access control and metrics.
+fn route_of(url: &str) -> &'static str {
+    let path = url.split('?').next().unwrap_or("");
+    if let Some(route) = ROUTES.iter().find(|route| **route == path) {
+        return route;
+    }
+    if path.starts_with("/docs/") {
+        return "/docs";
+    }
+    "other"
+}
+
+fn handle(state: &AppState, request: &Request, route: &str) -> HttpResponse {
+    let url = request.url();
+    let served = state.current();
+
+    match route {
+        "/" => send_asset(request, content::index_html(), "text/html; charset=utf-8"),
+        "/api/search" => handle_search(state, &served, request),
+        "/api/click" => {
+            let params = parse_query_string(url.split('?').nth(1).unwrap_or(""));
+            match params.get("doc") {
+                Some(doc) => {
+                    state.events.record(&Event::Click {
+                        timestamp: Utc::now(),
+                        query: params.get("q").map(String::as_str).unwrap_or(""),
+                        doc,
+                        rank: params.get("rank").and_then(|r| r.parse().ok()),
+                    });
+                    Response::from_string("").with_status_code(204)
+                }
+                None => Response::from_string("Missing doc parameter").with_status_code(400),
+            }
+        }
+        "/healthz" => Response::from_string("ok"),
+        "/readyz" => {
+            if served.index.docs.is_empty() {
+                Response::from_string("index is empty").with_status_code(503)
+            } else {
+                Response::from_string("ready")
+            }
+        }
+        "/api/info" => {
+            let info = IndexInfo {
+                meta: &served.index.meta,
+                documents: served.index.docs.len(),
+                size_bytes: storage::size_on_disk(),
+                generation: served.generation,
+                loaded_at: served.loaded_at,
+            };
+            match serde_json::to_vec(&info) {
+                Ok(json) => send_body(request, &json, "application/json", None),
+                Err(_) => Response::from_string("Failed to encode info").with_status_code(500),
+            }
+        }
+        "/admin/reload" => {
+            if *request.method() != Method::Post {
+                return Response::from_string("Use POST").with_status_code(405);
+            }
+            match state.reload() {
+                Ok(served) => Response::from_string(format!(
+                    "{{\"documents\":{},\"generation\":{}}}",
+                    served.index.docs.len(), served.generation,
+                )).with_header(Header::from_bytes("Content-Type", "application/json").unwrap()),
+                Err(e) => {
+                    error!(error = %e, "Index reload failed, keeping the current index");
+                    Response::from_string(format!("Reload failed: {e}")).with_status_code(500)
+                }
+            }
+        }
+        "/metrics" => send_body(
+            request,
+            state.metrics.render(state.cache.stats()).as_bytes(),
+            "text/plain; version=0.0.4",
+            None,
+        ),
+        "/docs" => {
+            let file_path_str = url.strip_prefix("/docs/").unwrap_or("");
             let decoded_path = urlencoding::decode(file_path_str)
                 .unwrap_or_else(|_| file_path_str.to_string().into());
             let file_path = Path::new(decoded_path.as_ref());
 
-            if file_path.exists() && file_path.is_file() {
-                match fs::read_to_string(file_path) {
+            // only indexed documents are served, never arbitrary files such as the config
+            let escapes = file_path.is_absolute()
+                || file_path.components().any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)));
+            if !escapes && served.index.docs.contains_key(file_path) && file_path.is_file() {
+                match fs::read(file_path) {
                     Ok(content) => {
-                        let content_type = if file_path.extension().map_or(false, |e| e == "xhtml") {
+                        let content_type = if file_path.extension().is_some_and(|e| e == "xhtml") {
                             "application/xhtml+xml"
                         } else {
                             "text/html"
                         };
-                        let response = Response::from_string(content)
-                            .with_header(Header::from_bytes("Content-Type", content_type).unwrap());
-                        request.respond(response)?;
-                    }
-                    Err(_) => {
-                        let response = Response::from_string("Failed to read file").with_status_code(500);
-                        request.respond(response)?;
+                        send_body(request, &content, content_type, Some(&content::etag(&content)))
                     }
+                    Err(_) => Response::from_string("Failed to read file").with_status_code(500),
                 }
             } else {
-                let response = Response::from_string("File not found").with_status_code(404);
-                request.respond(response)?;
+                Response::from_string("File not found").with_status_code(404)
             }
-            continue;
         }
+        _ => Response::from_string("Not Found").with_status_code(404),
+    }
+}
 
-        let response = Response::from_string("Not Found").with_status_code(404);
-        request.respond(response)?;
+fn handle_search(state: &AppState, served: &ServedIndex, request: &Request) -> HttpResponse {
+    let url = request.url();
+    let started = Instant::now();
+    let mut params = parse_query_string(url.split('?').nth(1).unwrap_or(""));
+    let ranges = match RangeFilter::take_from(&mut params) {
+        Ok(ranges) => ranges,
+        Err(e) => return Response::from_string(e).with_status_code(400),
+    };
+    let sort = match params.get("sort").map(|s| s.parse::<SortOrder>()).transpose() {
+        Ok(sort) => sort.unwrap_or_default(),
+        Err(e) => return Response::from_string(e).with_status_code(400),
+    };
+    let raw_query = params.get("q").map(String::as_str).unwrap_or("");
+    let search_terms = tokenize_query(raw_query);
+    let explain_hits = params.get("explain").is_some_and(|v| v == "true" || v == "1");
+    let filter = FacetFilter::from_params(&params);
+    let facet_fields = params.get("facets").map(|f| facets::split_list(f));
+
+    // terms are already normalized, so "Buffer  data" and "buffer data" share an entry
+    let cache_key = format!(
+        "{}|explain={}|filter={}|ranges={}|sort={}|facets={}",
+        search_terms.join(" "), explain_hits, filter.cache_key(), ranges.cache_key(), sort,
+        facet_fields.as_deref().unwrap_or_default().join(","),
+    );
+    let cached = state.cache.get(&cache_key, served.generation);
+    let cache_status = if cached.is_some() { "HIT" } else { "MISS" };
+    let result = match cached {
+        Some(result) => Ok(result),
+        None => {
+            let (hits, total, counts) = info_span!("query", terms = ?search_terms).in_scope(|| {
+                let query = SearchQuery { terms: &search_terms, explain_hits, filter: &filter, ranges: &ranges, sort };
+                search(&served.index, &query, facet_fields.as_deref())
+            });
+            let body = match &counts {
+                Some(facets) => serde_json::to_string(&FacetedResults { results: &hits, facets }),
+                None => serde_json::to_string(&hits),
+            };
+            body.map(|body| {
+                let result = Arc::new(CachedSearch {
+                    etag: content::etag(body.as_bytes()),
+                    body,
+                    total,
+                    results: hits.into_iter().map(|hit| hit.path).collect(),
+                });
+                state.cache.insert(cache_key, result.clone(), served.generation);
+                result
+            })
+        }
+    };
+
+    match result {
+        Ok(result) => {
+            debug!(terms = ?search_terms, hits = result.total, cache = cache_status, "Search finished");
+            state.events.record(&Event::Query {
+                timestamp: Utc::now(),
+                query: raw_query,
+                terms: &search_terms,
+                hits: result.total,
+                latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+                results: result.results.iter().map(String::as_str).collect(),
+            });
+            send_body(request, result.body.as_bytes(), "application/json", Some(&result.etag))
+                .with_header(Header::from_bytes("X-Cache", cache_status).unwrap())
+        }
+        Err(_) => Response::from_string("Failed to encode results").with_status_code(500),
     }
-    Ok(())
+}
+
+/// Sends `body`, compressed if the client accepts it. With an `etag` the client
+/// may revalidate, and gets an empty 304 when its copy is still current.
+fn send_body(request: &Request, body: &[u8], content_type: &str, etag: Option<&str>) -> HttpResponse {
+    let encoding = content::applied(content::negotiate(header_value(request, "Accept-Encoding")), body.len());
+    if let Some(etag) = etag.map(|etag| encoding.etag(etag))
+        && content::matches_if_none_match(header_value(request, "If-None-Match"), &etag)
+    {
+        return not_modified(&etag);
+    }
+    let compressed = content::compress(body, encoding, false).unwrap_or_else(|e| {
+        warn!(error = %e, "Compression failed, sending uncompressed");
+        None
+    });
+    match compressed {
+        Some(data) => encoded_response(data, encoding, content_type, etag),
+        None => encoded_response(body.to_vec(), Encoding::Identity, content_type, etag),
+    }
+}
+
+/// Like `send_body`, but for assets embedded in the binary whose compressed forms are prepared once.
+fn send_asset(request: &Request, asset: &EmbeddedAsset, content_type: &str) -> HttpResponse {
+    let encoding = content::negotiate(header_value(request, "Accept-Encoding"));
+    let (data, encoding) = match asset.encoded(encoding) {
+        Some(data) => (data, encoding),
+        None => (asset.body, Encoding::Identity),
+    };
+    let etag = encoding.etag(&asset.etag);
+    if content::matches_if_none_match(header_value(request, "If-None-Match"), &etag) {
+        return not_modified(&etag);
+    }
+    encoded_response(data.to_vec(), encoding, content_type, Some(&asset.etag))
+}
+
+fn encoded_response(data: Vec<u8>, encoding: Encoding, content_type: &str, etag: Option<&str>) -> HttpResponse {
+    let mut response = Response::from_data(data)
+        .with_header(Header::from_bytes("Content-Type", content_type).unwrap())
+        .with_header(Header::from_bytes("Vary", "Accept-Encoding").unwrap());
+    if let Some(value) = encoding.header_value() {
+        response = response.with_header(Header::from_bytes("Content-Encoding", value).unwrap());
+    }
+    if let Some(etag) = etag {
+        // cacheable, but always revalidated so a reindex or redeploy shows up immediately
+        response = response
+            .with_header(Header::from_bytes("ETag", encoding.etag(etag)).unwrap())
+            .with_header(Header::from_bytes("Cache-Control", "no-cache").unwrap());
+    }
+    response
+}
+
+fn not_modified(etag: &str) -> HttpResponse {
+    Response::from_data(Vec::new()).with_status_code(304)
+        .with_header(Header::from_bytes("ETag", etag).unwrap())
+        .with_header(Header::from_bytes("Cache-Control", "no-cache").unwrap())
+        .with_header(Header::from_bytes("Vary", "Accept-Encoding").unwrap())
+}
+
+#[derive(Serialize)]
+struct IndexInfo<'a> {
+    #[serde(flatten)]
+    meta: &'a IndexMeta,
+    documents: usize,
+    size_bytes: u64,
+    generation: u64,
+    loaded_at: DateTime<Utc>,
+}
+
+/// One search result, serialized as `[path, score, snippet]` with the score
+/// explanation appended as a fourth element when requested.
+struct SearchHit {
+    path: String,
+    score: f32,
+    snippet: String,
+    explanation: Option<Explanation>,
+}
+
+impl Serialize for SearchHit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.explanation.is_some() { 4 } else { 3 };
+        let mut tuple = serializer.serialize_tuple(len)?;
+        tuple.serialize_element(&self.path)?;
+        tuple.serialize_element(&self.score)?;
+        tuple.serialize_element(&self.snippet)?;
+        if let Some(explanation) = &self.explanation {
+            tuple.serialize_element(explanation)?;
+        }
+        tuple.end()
+    }
+}
+
+/// The search response when facet counts were requested.
+#[derive(Serialize)]
+struct FacetedResults<'a> {
+    results: &'a [SearchHit],
+    facets: &'a FacetCounts,
+}
+
+/// What to match and how to order it, as parsed from `/api/search` parameters.
+struct SearchQuery<'a> {
+    terms: &'a [String],
+    explain_hits: bool,
+    filter: &'a FacetFilter,
+    ranges: &'a RangeFilter,
+    sort: SortOrder,
+}
+
+/// Returns the first page of hits, how many documents matched in all, and the
+/// facet counts if asked for.
+fn search(index: &InvertedIndex, query: &SearchQuery, facet_fields: Option<&[String]>) -> (Vec<SearchHit>, usize, Option<FacetCounts>) {
+    let search_terms = query.terms;
+    // precompute
+    let term_stats = TermStats::new(search_terms, index);
+    let ranking = Ranking::default();
+
+    let matched: Vec<_> = matches(index, search_terms, ranking, &term_stats).into_iter()
+        .filter(|(_, doc, _)| query.ranges.matches(doc))
+        .collect();
+    let counts = facet_fields.map(|fields| {
+        let docs: Vec<_> = matched.iter().map(|(_, doc, _)| *doc).collect();
+        facets::count(&docs, fields, query.filter)
+    });
+
+    let mut ranked: Vec<_> = matched.into_iter().filter(|(_, doc, _)| query.filter.matches(doc)).collect();
+    let total = ranked.len();
+    query.sort.sort(&mut ranked);
+    ranked.truncate(20);
+    let hits = ranked.into_iter().map(|(path, stats, score)| {
+        let first_match = search_terms.iter().find(|term| stats.tf.contains_key(*term));
+        SearchHit {
+            path: path.display().to_string().replace('\\', "/"),
+            score,
+            snippet: first_match.map(|term| make_snippet(path, term)).unwrap_or_default(),
+            explanation: query.explain_hits.then(|| ranking.explain(search_terms, stats, &term_stats)),
+        }
+    }).collect();
+    (hits, total, counts)
+}
+
+fn make_snippet(path: &Path, term: &str) -> String {
+    let mut snippet = String::new();
+    if let Ok(content) = fs::read_to_string(path) {
+        let content_upper = content.to_uppercase();
+        if let Some(pos) = content_upper.find(term) {
+            // char boundaries
+            let char_pos = content_upper[..pos].chars().count();
+            
+            let start_char = char_pos.saturating_sub(15);
+            let end_char = (char_pos + term.chars().count() + 40).min(content.chars().count());
+            
+            snippet = content.chars().skip(start_char).take(end_char - start_char).collect::<String>();
+            snippet = snippet.replace('\n', " ").trim().to_string();
+            
+            if start_char > 0 { snippet = format!("...{}", snippet); }
+            if end_char < content.chars().count() { snippet = format!("{}...", snippet); }
+        }
+    }
+    snippet
+}
+
+fn parse_query_string(query_string: &str) -> HashMap<String, String> {
+    query_string.split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if key.is_empty() { return None; }
+            let decode = |s: &str| {
+                let s = s.replace('+', " ");
+                urlencoding::decode(&s).map(|d| d.into_owned()).unwrap_or(s)
+            };
+            Some((decode(key), decode(value)))
+        })
+        .collect()
 }
@@ -1,15 +1,19 @@
 use std::collections::HashSet;
 use std::sync::OnceLock;
 
+const STOPWORDS: &[&str] = &[
+    "A", "AN", "AND", "ARE", "AS", "AT", "BE", "BUT", "BY",
+    "FOR", "IF", "IN", "INTO", "IS", "IT", "NO", "NOT", "OF",
+    "ON", "OR", "SUCH", "THAT", "THE", "THEIR", "THEN", "THERE",
+    "THESE", "THEY", "THIS", "TO", "WAS", "WILL", "WITH"
+];
+
 pub fn is_stopword(token: &str) -> bool {
-    static STOPWORDS: OnceLock<HashSet<&'static str>> = OnceLock::new();
-    let stopwords = STOPWORDS.get_or_init(|| {
-        [
-            "A", "AN", "AND", "ARE", "AS", "AT", "BE", "BUT", "BY",
-            "FOR", "IF", "IN", "INTO", "IS", "IT", "NO", "NOT", "OF",
-            "ON", "OR", "SUCH", "THAT", "THE", "THEIR", "THEN", "THERE",
-            "THESE", "THEY", "THIS", "TO", "WAS", "WILL", "WITH"
-        ].iter().cloned().collect()
-    });
+    static STOPWORD_SET: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    let stopwords = STOPWORD_SET.get_or_init(|| STOPWORDS.iter().cloned().collect());
     stopwords.contains(token)
 }
+
+pub fn count() -> usize {
+    STOPWORDS.len()
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use crate::model::{DocStats, InvertedIndex};
+
+pub const INDEX_PATH: &str = "index.json";
+pub const WAL_PATH: &str = "index.wal";
+
+/// A single change to the index recorded in the write-ahead log.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum WalEntry {
+    Put { path: PathBuf, stats: DocStats },
+    Delete { path: PathBuf },
+}
+
+impl WalEntry {
+    fn apply(self, index: &mut InvertedIndex) {
+        match self {
+            WalEntry::Put { path, stats } => {
+                index.docs.insert(path, stats);
+            }
+            WalEntry::Delete { path } => {
+                index.docs.remove(&path);
+            }
+        }
+    }
+}
+
+/// Combined size of the index snapshot and its WAL, in bytes.
+pub fn size_on_disk() -> u64 {
+    [INDEX_PATH, WAL_PATH].iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// Writes the index to a temp file next to `path`, fsyncs it and renames it
+/// over the old one, so readers only ever see a complete index.
+pub fn save_index(path: &Path, index: &InvertedIndex) -> io::Result<()> {
+    let tmp_path = tmp_path_for(path);
+    {
+        let file = fs::File::create(&tmp_path)?;
+        let mut writer = io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, index)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    sync_parent_dir(path)
+}
+
+pub fn load_index(path: &Path) -> io::Result<InvertedIndex> {
+    let file = fs::File::open(path).map_err(|e| {
+        io::Error::new(e.kind(), format!("cannot open {}: {e}", path.display()))
+    })?;
+    serde_json::from_reader(io::BufReader::new(file)).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is corrupt or truncated: {e}", path.display()),
+        )
+    })
+}
+
+/// Loads the snapshot at `index_path` and replays any WAL entries on top of it.
+pub fn open_index(index_path: &Path, wal_path: &Path) -> io::Result<InvertedIndex> {
+    let mut index = load_index(index_path)?;
+    replay_wal(wal_path, &mut index)?;
+    Ok(index)
+}
+
+/// Applies every complete entry in the WAL to `index` and returns how many were applied.
+///
+/// A missing WAL is treated as empty. A trailing line without a newline is the
+/// remains of an interrupted append and is ignored; any other malformed line is an error.
+pub fn replay_wal(wal_path: &Path, index: &mut InvertedIndex) -> io::Result<usize> {
+    let file = match fs::File::open(wal_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut reader = io::BufReader::new(file);
+    let mut line = String::new();
+    let mut applied = 0;
+    let mut line_no = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_no += 1;
+        if !line.ends_with('\n') {
+            warn!(path = %wal_path.display(), line = line_no, "Ignoring incomplete entry at end of WAL");
+            break;
+        }
+        let entry: WalEntry = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} line {line_no} is corrupt: {e}", wal_path.display()),
+            )
+        })?;
+        entry.apply(index);
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+pub struct Wal {
+    file: fs::File,
+}
+
+impl Wal {
+    /// Opens the WAL for appending, first cutting off any incomplete entry an
+    /// interrupted append left at the end, so new entries start on a line of their own.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = fs::OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let complete = complete_len(&file)?;
+        if complete < file.metadata()?.len() {
+            warn!(path = %path.display(), "Truncating incomplete entry at end of WAL");
+            file.set_len(complete)?;
+            file.sync_data()?;
+        }
+        Ok(Self { file })
+    }
+
+    /// Appends an entry and fsyncs it before returning.
+    pub fn append(&mut self, entry: &WalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+}
+
+/// Length of `file` up to and including its last newline.
+fn complete_len(file: &fs::File) -> io::Result<u64> {
+    let mut reader = io::BufReader::new(file);
+    let mut line = Vec::new();
+    let mut complete = 0;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 || !line.ends_with(b"\n") {
+            return Ok(complete);
+        }
+        complete += read as u64;
+    }
+}
+
+/// Drops all WAL entries, e.g. after a full snapshot has been saved.
+pub fn reset_wal(wal_path: &Path) -> io::Result<()> {
+    match fs::remove_file(wal_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => sync_parent_dir(wal_path),
+    }
+}
+
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::time::SystemTime;
+use tiny_http::SslConfig;
+use crate::config::TlsConfig;
+
+/// The certificate chain and key read from disk, plus their modification times
+/// so a change on disk can be detected.
+#[derive(Clone)]
+pub struct TlsMaterial {
+    certificate: Vec<u8>,
+    private_key: Vec<u8>,
+    pub modified: Option<(SystemTime, SystemTime)>,
+}
+
+impl TlsMaterial {
+    pub fn load(config: &TlsConfig) -> io::Result<Self> {
+        let modified = modified(config);
+        let certificate = fs::read(&config.cert_path).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot read {}: {e}", config.cert_path.display()))
+        })?;
+        let private_key = fs::read(&config.key_path).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot read {}: {e}", config.key_path.display()))
+        })?;
+
+        // tiny_http panics on keys it cannot parse, so check them the same way first
+        validate(&certificate, &private_key).map_err(|e| {
+            invalid(format!("{} / {}: {e}", config.cert_path.display(), config.key_path.display()))
+        })?;
+
+        Ok(Self { certificate, private_key, modified })
+    }
+
+    pub fn ssl_config(&self) -> SslConfig {
+        SslConfig {
+            certificate: self.certificate.clone(),
+            private_key: self.private_key.clone(),
+        }
+    }
+}
+
+/// Modification times of the certificate and key files, if both can be read.
+pub fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let cert = fs::metadata(&config.cert_path).and_then(|m| m.modified()).ok()?;
+    let key = fs::metadata(&config.key_path).and_then(|m| m.modified()).ok()?;
+    Some((cert, key))
+}
+
+/// Parses the chain and key as tiny_http will, and builds a rustls config from
+/// them, so a truncated file or a key that does not match the certificate is
+/// rejected here rather than when the server is bound.
+fn validate(certificate: &[u8], private_key: &[u8]) -> Result<(), String> {
+    let chain: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut &certificate[..])
+        .map_err(|e| format!("unreadable certificate: {e}"))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if chain.is_empty() {
+        return Err("no PEM certificate found".to_string());
+    }
+
+    let pkcs8 = rustls_pemfile::pkcs8_private_keys(&mut &private_key[..])
+        .map_err(|e| format!("unreadable PKCS#8 private key: {e}"))?;
+    // tiny_http only looks for an RSA key when there is no PKCS#8 one
+    let key = match pkcs8.into_iter().next() {
+        Some(key) => key,
+        None => rustls_pemfile::rsa_private_keys(&mut &private_key[..])
+            .map_err(|e| format!("unreadable RSA private key: {e}"))?
+            .into_iter()
+            .next()
+            .ok_or("no PKCS#8 or RSA PEM private key found")?,
+    };
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, rustls::PrivateKey(key))
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}